@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+use atmega::prelude::*;
+
+const LED_BUILTIN: Pin = Pin::D13;
+
+#[atmega::entry]
+fn setup() -> u32 {
+    Serial::begin(9600);
+    pin_mode(LED_BUILTIN, PinMode::OUTPUT);
+    0
+}
+
+#[atmega::loop_fn]
+fn run(blinks: &mut u32) {
+    digital_toggle(LED_BUILTIN);
+    *blinks += 1;
+    println!("blink {}", blinks);
+    delay(1000);
+}
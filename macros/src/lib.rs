@@ -98,7 +98,31 @@ impl Interrupt {
 /// }
 /// ```
 ///
+/// 
+/// # Conflicts
+/// Some vectors are handled by the crate when certain features are enabled, such as `TIMER0_OVF` with `millis`.
+/// Defining a handler for one of these fails to compile, with a message naming the feature and an alternative.
+/// 
+/// # Handler State
+/// `static mut` items at the start of the handler are turned into `&mut` references that only the handler can reach,
+/// so state can be kept between calls without a global [`Volatile`].
+/// The references only live until the handler returns, so they can't be stored anywhere else.
+/// This relies on interrupts staying disabled while a handler runs, so it can't be called again until it returns.
+/// Calling [`interrupts::enable()`] inside such a handler lets it be entered again while the first call still holds
+/// the references, which is undefined behavior.
+/// ```
+/// #[interrupt]
+/// unsafe fn TIMER1_OVF() {
+///     static mut COUNT: u32 = 0;
+/// 
+///     // COUNT has the type &mut u32
+///     *COUNT += 1;
+/// }
+/// ```
+///
 /// [`atmega::interrupts::Interrupt`]: enum@crate::interrupts::Interrupt
+/// [`Volatile`]: struct@crate::volatile::Volatile
+/// [`interrupts::enable()`]: fn@crate::interrupts::enable
 #[proc_macro_attribute]
 pub fn interrupt(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut f: syn::ItemFn = syn::parse(item).expect("'#[interrupt]' must be called on a function");
@@ -142,15 +166,249 @@ pub fn interrupt(attr: TokenStream, item: TokenStream) -> TokenStream {
     };
     let vector = interrupt.vector();
 
+    // Pull the leading `static mut` items out of the body
+    let mut statics = Vec::new();
+    while let Some(syn::Stmt::Item(syn::Item::Static(item))) = f.block.stmts.first() {
+        if item.mutability.is_none() {
+            break;
+        }
+        if let syn::Stmt::Item(syn::Item::Static(item)) = f.block.stmts.remove(0) {
+            statics.push(item);
+        }
+    }
+
+    // The body is moved into an inner function that takes each static as a `&mut` argument.
+    // The references can't outlive that call, so they can't be stashed away and aliased by the next one.
+    if !statics.is_empty() {
+        let params = statics.iter().map(|item| {
+            let syn::ItemStatic { ident, ty, .. } = item;
+            quote::quote_spanned!(item.span()=> #[allow(non_snake_case)] #ident: &mut #ty)
+        });
+        // Each static is declared in its own block, so it doesn't clash with the argument of the same name
+        let args = statics.iter().map(|item| {
+            let syn::ItemStatic { attrs, ident, ty, expr, .. } = item;
+            quote::quote_spanned!(item.span()=> {
+                #(#attrs)*
+                static mut #ident: #ty = #expr;
+                &mut *::core::ptr::addr_of_mut!(#ident)
+            })
+        });
+        let inner = Ident::new(&format!("__atmega_{}_handler", f.sig.ident), Span::call_site());
+        let output = &f.sig.output;
+        let stmts = &f.block.stmts;
+        f.block = syn::parse_quote!({
+            #[inline(always)]
+            unsafe fn #inner(#(#params),*) #output {
+                #(#stmts)*
+            }
+
+            #inner(#(#args),*)
+        });
+    }
+
     // No idea how this works, just let auto-complete fill in the gaps
     // Adds 'pub' to make public and 'extern "avr-interrupt" for the linker
     f.vis = Visibility::Public(VisPublic { pub_token: Pub(fnspan) });
     f.sig.abi = Some(syn::Abi { name: Some(LitStr::new("avr-interrupt", fnspan)), extern_token: syn::token::Extern { span: fnspan } });
 
+    // Fails with a message naming the feature if the crate already handles this vector
+    let number = interrupt as u8;
+    let check = quote::quote_spanned!(f.sig.ident.span()=>
+        const _: () = ::atmega::interrupts::__assert_unowned(#number);
+    );
+
     quote::quote!(
+        #check
+
         #[doc(hidden)]
         #[allow(non_snake_case)]
         #[export_name = #vector]
         #f
     ).into()
 }
+
+/// Marks the entry point of the program, replacing `run!`.
+///
+/// Can be used in one of two ways:
+/// - On `fn main() -> !`, which is called after the chip is initialized.
+/// - On a setup function such as `fn setup() -> State`, which is called once after the chip is initialized.
+///   The function marked with [`#[loop_fn]`](macro@loop_fn) is then called forever,
+///   with a mutable reference to the returned state if there is one.
+///
+/// # Example
+/// ```
+/// #[atmega::entry]
+/// fn setup() -> u8 {
+///     pin_mode(Pin::D13, PinMode::OUTPUT);
+///     0
+/// }
+///
+/// #[atmega::loop_fn]
+/// fn run(count: &mut u8) {
+///     *count = count.wrapping_add(1);
+///     digital_toggle(Pin::D13);
+///     delay(1000);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn entry(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let f: syn::ItemFn = match syn::parse(item) {
+        Ok(f) => f,
+        Err(_) => {
+            return syn::parse::Error::new(Span::call_site(), "'#[entry]' must be called on a function")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let fnspan = f.span();
+
+    if !attr.is_empty() {
+        return syn::parse::Error::new(Span::call_site(), "This macro accepts no arguments")
+            .to_compile_error()
+            .into()
+    }
+
+    let valid = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.sig.unsafety.is_none()
+        && f.sig.abi.is_none()
+        && f.sig.inputs.is_empty()
+        && f.sig.variadic.is_none()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none();
+
+    if !valid {
+        return syn::parse::Error::new(fnspan, "#[entry] functions must have the function signature fn() -> ! or fn() [-> State]")
+            .to_compile_error()
+            .into()
+    }
+
+    let ident = &f.sig.ident;
+
+    let body = match f.sig.output {
+        syn::ReturnType::Type(_, ref ty) if matches!(**ty, syn::Type::Never(_)) => {
+            quote::quote_spanned!(ident.span()=> #ident())
+        },
+        syn::ReturnType::Type(_, ref ty) if !matches!(**ty, syn::Type::Tuple(ref tuple) if tuple.elems.is_empty()) => {
+            // The call to `__atmega_loop` is given the span of the state type,
+            // so a missing `#[loop_fn]` or mismatched state points here
+            quote::quote_spanned!(ty.span()=>
+                let mut state = #ident();
+                loop { __atmega_loop(&mut state) }
+            )
+        },
+        _ => {
+            quote::quote_spanned!(ident.span()=>
+                #ident();
+                loop { __atmega_loop() }
+            )
+        },
+    };
+
+    quote::quote!(
+        #f
+
+        #[doc(hidden)]
+        #[export_name = "main"]
+        pub extern "C" fn __atmega_main() -> ! {
+            ::atmega::_init();
+            #body
+        }
+    ).into()
+}
+
+/// Marks the function called forever after the [`#[entry]`](macro@entry) setup function.
+///
+/// Takes either no arguments, or a `&mut` reference to the state returned by the setup function.
+///
+/// # Example
+/// ```
+/// #[atmega::entry]
+/// fn setup() {
+///     pin_mode(Pin::D13, PinMode::OUTPUT);
+/// }
+///
+/// #[atmega::loop_fn]
+/// fn run() {
+///     digital_toggle(Pin::D13);
+///     delay(1000);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn loop_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let f: syn::ItemFn = match syn::parse(item) {
+        Ok(f) => f,
+        Err(_) => {
+            return syn::parse::Error::new(Span::call_site(), "'#[loop_fn]' must be called on a function")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let fnspan = f.span();
+
+    if !attr.is_empty() {
+        return syn::parse::Error::new(Span::call_site(), "This macro accepts no arguments")
+            .to_compile_error()
+            .into()
+    }
+
+    let valid = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.sig.unsafety.is_none()
+        && f.sig.abi.is_none()
+        && f.sig.inputs.len() <= 1
+        && f.sig.variadic.is_none()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && match f.sig.output {
+            syn::ReturnType::Default => true,
+            syn::ReturnType::Type(_, ref ty) => matches!(**ty, syn::Type::Tuple(ref tuple) if tuple.elems.is_empty()),
+        };
+
+    if !valid {
+        return syn::parse::Error::new(fnspan, "#[loop_fn] functions must have the function signature fn([&mut State])")
+            .to_compile_error()
+            .into()
+    }
+
+    let ident = &f.sig.ident;
+
+    let wrapper = match f.sig.inputs.first() {
+        Some(syn::FnArg::Typed(arg)) => {
+            let ty = match *arg.ty {
+                syn::Type::Reference(ref reference) if reference.mutability.is_some() => &reference.elem,
+                _ => {
+                    return syn::parse::Error::new(arg.ty.span(), "#[loop_fn] state must be a mutable reference, such as &mut State")
+                        .to_compile_error()
+                        .into()
+                }
+            };
+
+            quote::quote_spanned!(arg.ty.span()=>
+                #[doc(hidden)]
+                #[inline(always)]
+                fn __atmega_loop(state: &mut #ty) {
+                    #ident(state)
+                }
+            )
+        },
+        Some(syn::FnArg::Receiver(receiver)) => {
+            return syn::parse::Error::new(receiver.span(), "#[loop_fn] functions can't take self")
+                .to_compile_error()
+                .into()
+        },
+        None => quote::quote_spanned!(ident.span()=>
+            #[doc(hidden)]
+            #[inline(always)]
+            fn __atmega_loop() {
+                #ident()
+            }
+        ),
+    };
+
+    quote::quote!(
+        #f
+
+        #wrapper
+    ).into()
+}
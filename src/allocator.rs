@@ -1,48 +1,226 @@
+//! A free-list heap allocator, allowing the use of `alloc` types such as `Vec` and `String`.
+//!
+//! The heap grows up from the end of static memory (`__heap_start`) towards the stack,
+//! always leaving at least [`MALLOC_MARGIN`] bytes below the stack pointer.
+//! Freed chunks are kept in a list sorted by address, and adjacent chunks are merged.
+//! Allocations use the first free chunk that is large enough, and only grow the heap if none are.
+//!
+//! # Example
+//! ```rust,no_run
+//! extern crate alloc;
+//! use alloc::vec::Vec;
+//!
+//! let mut readings = Vec::new();
+//! readings.push(analog_read(Pin::A0));
+//! ```
+
 extern crate alloc;
 
 use alloc::alloc::{GlobalAlloc, Layout};
-use core::ptr::{null_mut, NonNull};
+use core::cell::UnsafeCell;
+use core::mem::{ align_of, size_of };
+use core::ptr::null_mut;
+use crate::interrupts::{ self, State };
+use crate::memory::stack_pointer;
+
+/// The number of bytes kept free between the top of the heap and the stack pointer.
+pub const MALLOC_MARGIN: usize = 32;
 
-const MALLOC_MARGIN: usize = 32;
-const HEAP_SIZE: usize = 512;
+/// The size stored before every chunk.
+const HEADER: usize = size_of::<usize>();
+/// The smallest chunk that can be stored in the free list.
+const MIN_CHUNK: usize = size_of::<Chunk>();
+
+extern "C" {
+    /// The end of static memory, defined by the linker script.
+    static mut __heap_start: u8;
+}
+
+/// A chunk of the heap.
+/// Allocated chunks only use the `size` header, with the allocation starting where `next` would be.
+#[repr(C)]
+struct Chunk {
+    /// The size of the chunk, including the header
+    size: usize,
+    /// The next free chunk, higher in memory
+    next: *mut Chunk,
+}
 
 struct Heap {
-    pub used: usize,
-    pub free: FreeList,
+    /// The free chunk lowest in memory
+    free: *mut Chunk,
+    /// The top of the heap, or null if nothing has been allocated yet
+    brk: *mut u8,
+    /// The bytes currently allocated, including headers
+    used: usize,
+    /// The most bytes that have been allocated at once, including headers
+    peak: usize,
 }
 
-struct FreeList {
-    pub first: Chunk,
-    pub bottom: *mut u8,
-    pub top: *mut u8,
+impl Heap {
+    /// Returns the top of the heap, starting it at `__heap_start` if needed.
+    unsafe fn brk(&mut self) -> *mut u8 {
+        if self.brk.is_null() {
+            self.brk = core::ptr::addr_of_mut!(__heap_start);
+        }
+        self.brk
+    }
+
+    /// Allocates a chunk of the given total size, returning the address of the chunk.
+    unsafe fn alloc(&mut self, size: usize) -> *mut Chunk {
+        let mut prev: *mut Chunk = null_mut();
+        let mut chunk = self.free;
+
+        // First fit
+        while !chunk.is_null() {
+            if (*chunk).size >= size {
+                if (*chunk).size - size >= MIN_CHUNK {
+                    // Split the chunk, taking the top so the list doesn't have to be relinked
+                    (*chunk).size -= size;
+                    let new = (chunk as *mut u8).add((*chunk).size) as *mut Chunk;
+                    (*new).size = size;
+                    return new;
+                }
+
+                // Close enough to use the whole chunk
+                if prev.is_null() {
+                    self.free = (*chunk).next;
+                } else {
+                    (*prev).next = (*chunk).next;
+                }
+                return chunk;
+            }
+
+            prev = chunk;
+            chunk = (*chunk).next;
+        }
+
+        // Nothing in the free list fits, so grow the heap towards the stack
+        let brk = self.brk();
+        let limit = stack_pointer().saturating_sub(MALLOC_MARGIN);
+        if (brk as usize).saturating_add(size) > limit {
+            return null_mut();
+        }
+
+        let chunk = brk as *mut Chunk;
+        (*chunk).size = size;
+        self.brk = brk.add(size);
+        chunk
+    }
+
+    /// Inserts a chunk into the free list, merging it with its neighbors.
+    unsafe fn free(&mut self, chunk: *mut Chunk) {
+        (*chunk).next = null_mut();
+
+        let mut prev: *mut Chunk = null_mut();
+        let mut next = self.free;
+        while !next.is_null() && next < chunk {
+            prev = next;
+            next = (*next).next;
+        }
+
+        // Merge with the following chunk
+        if !next.is_null() && (chunk as *mut u8).add((*chunk).size) == next as *mut u8 {
+            (*chunk).size += (*next).size;
+            (*chunk).next = (*next).next;
+        } else {
+            (*chunk).next = next;
+        }
+
+        // Merge with the preceding chunk
+        if prev.is_null() {
+            self.free = chunk;
+        } else if (prev as *mut u8).add((*prev).size) == chunk as *mut u8 {
+            (*prev).size += (*chunk).size;
+            (*prev).next = (*chunk).next;
+        } else {
+            (*prev).next = chunk;
+        }
+
+        self.trim();
+    }
+
+    /// Gives the last free chunk back to the stack if it is at the top of the heap.
+    unsafe fn trim(&mut self) {
+        let mut prev: *mut Chunk = null_mut();
+        let mut last = self.free;
+        if last.is_null() {
+            return;
+        }
+
+        while !(*last).next.is_null() {
+            prev = last;
+            last = (*last).next;
+        }
+
+        if (last as *mut u8).add((*last).size) == self.brk {
+            // Repaint the space so it isn't counted as used by the stack
+            #[cfg(feature = "stack-paint")]
+            crate::memory::paint(last as usize, self.brk as usize);
+
+            self.brk = last as *mut u8;
+            if prev.is_null() {
+                self.free = null_mut();
+            } else {
+                (*prev).next = null_mut();
+            }
+        }
+    }
 }
 
-struct Chunk {
-    pub size: usize,
-    pub next: Option<NonNull<u8>>,
+/// The global allocator, which manages the space between static memory and the stack.
+pub struct FreeList {
+    heap: UnsafeCell<Heap>,
 }
 
 impl FreeList {
+    /// Creates an empty heap.
     pub const fn empty() -> FreeList {
         FreeList {
-            first: Chunk {size: 0, next: None},
-            bottom: null_mut(),
-            top: null_mut(),
+            heap: UnsafeCell::new(Heap {
+                free: null_mut(),
+                brk: null_mut(),
+                used: 0,
+                peak: 0,
+            }),
         }
     }
-
-    pub fn new(bottom: *mut u8, top: *mut u8) -> FreeList {
-        FreeList { bottom, top, first: Chunk {size: 0, next: None} }
-    }
 }
 
 unsafe impl GlobalAlloc for FreeList {
-    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
-        null_mut()
+    /// Returns null if the heap would grow within `MALLOC_MARGIN` of the stack,
+    /// or if the alignment is larger than that of `usize`.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > align_of::<usize>() {
+            return null_mut();
+        }
+
+        // Round up so every chunk keeps the header aligned
+        let size = match layout.size().checked_add(HEADER + align_of::<usize>() - 1) {
+            Some(size) => (size & !(align_of::<usize>() - 1)).max(MIN_CHUNK),
+            None => return null_mut(),
+        };
+
+        interrupts::without(State::Restore, || {
+            let heap = &mut *self.heap.get();
+            let chunk = heap.alloc(size);
+            if chunk.is_null() {
+                return null_mut();
+            }
+
+            heap.used += (*chunk).size;
+            heap.peak = heap.peak.max(heap.used);
+            (chunk as *mut u8).add(HEADER)
+        })
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        panic!("dealloc should not be called")
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        interrupts::without(State::Restore, || {
+            let heap = &mut *self.heap.get();
+            let chunk = ptr.sub(HEADER) as *mut Chunk;
+            heap.used -= (*chunk).size;
+            heap.free(chunk);
+        });
     }
 }
 
@@ -51,3 +229,36 @@ unsafe impl Sync for FreeList {}
 
 #[global_allocator]
 static ALLOCATOR: FreeList = FreeList::empty();
+
+/// Runs a function with access to the global heap.
+fn with_heap<F: FnOnce(&mut Heap) -> R, R>(f: F) -> R {
+    interrupts::without(State::Restore, || unsafe { f(&mut *ALLOCATOR.heap.get()) })
+}
+
+/// The bytes currently allocated on the heap, including the header of each allocation.
+pub fn heap_used() -> usize {
+    with_heap(|heap| heap.used)
+}
+
+/// The most bytes that have been allocated on the heap at once, including the header of each allocation.
+pub fn heap_peak() -> usize {
+    with_heap(|heap| heap.peak)
+}
+
+/// The bytes in freed chunks below the top of the heap, which can be reused without growing the heap.
+pub fn heap_free() -> usize {
+    with_heap(|heap| unsafe {
+        let mut total = 0;
+        let mut chunk = heap.free;
+        while !chunk.is_null() {
+            total += (*chunk).size;
+            chunk = (*chunk).next;
+        }
+        total
+    })
+}
+
+/// The address of the top of the heap.
+pub(crate) fn heap_end() -> usize {
+    with_heap(|heap| unsafe { heap.brk() as usize })
+}
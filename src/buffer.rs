@@ -1,11 +1,18 @@
 //! Simple implementation of a byte buffer with a variable length.
 //! 
 //! This is an adaptation of the `USART_BUFFER` from [`avr_328p_usart`](https://github.com/johncobb/avr_328p_usart)
+//!
+//! [`Queue`] is a lock-free version for sharing data between an interrupt and the main loop.
 
+use core::cell::UnsafeCell;
 use core::mem::MaybeUninit;
+use core::ptr::{ read_volatile, write_volatile };
+use core::sync::atomic::{ compiler_fence, Ordering };
 
 /// Byte buffer of variable length.
 /// Default length is 32.
+///
+/// One slot is kept empty to tell a full buffer from an empty one, so the buffer holds up to `SIZE - 1` items.
 #[derive(Debug, Clone, Copy)]
 pub struct Buffer<T: Copy, const SIZE: usize = 32> {
     head: usize,
@@ -17,6 +24,9 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     /// Maximum size of the buffer
     pub const MAX_SIZE: usize = SIZE;
 
+    /// The number of items the buffer can hold before writes are ignored.
+    pub const CAPACITY: usize = SIZE - 1;
+
     /// Creates a new buffer set to all 0s
     #[inline(always)]
     pub const fn new() -> Self {
@@ -30,10 +40,10 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     /// Creates a blank buffer and writes the contents of the passed slice into the buffer.
     /// 
     /// # Panics
-    /// Will panic if the length of the slice is larger than the maximum size of the buffer.
+    /// Will panic if the length of the slice is larger than the capacity of the buffer.
     pub fn copy_from_slice(data: &[T]) -> Self {
-        if data.len() > SIZE {
-            panic!("Slice larger than Buffer MAX_SIZE");
+        if data.len() > Self::CAPACITY {
+            panic!("Slice larger than Buffer CAPACITY");
         }
         let mut new = Self::new();
         for byte in data {
@@ -46,16 +56,46 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     /// Writes a byte to the head of the buffer.
     /// Does not do anything if the buffer is full.
     pub fn write(&mut self, value: T) {
+        let _ = self.try_write(value);
+    }
+
+    /// Writes a byte to the head of the buffer.
+    /// Returns the value back if the buffer is full.
+    pub fn try_write(&mut self, value: T) -> Result<(), T> {
         let i = (self.head + 1) % SIZE;
 
         // if we should be storing the received character into the location
         // just before the tail (meaning that the head would advance to the
         // current location of the tail), we're about to overflow the buffer
         // and so we don't write the character or advance the head.
-        if i != self.tail {
-            self.buffer[self.head].write(value);
-            self.head = i;
+        if i == self.tail {
+            return Err(value);
         }
+
+        self.buffer[self.head].write(value);
+        self.head = i;
+        Ok(())
+    }
+
+    /// Writes a byte to the head of the buffer, making room by dropping the oldest byte if the buffer is full.
+    /// Returns the dropped byte, if there was one.
+    pub fn overwrite(&mut self, value: T) -> Option<T> {
+        let dropped = if self.is_full() { self.read() } else { None };
+        self.write(value);
+        dropped
+    }
+
+    /// Writes every item in the slice to the buffer.
+    /// Returns `Err(())` without writing anything if there isn't enough space for the whole slice.
+    pub fn extend_from_slice(&mut self, data: &[T]) -> Result<(), ()> {
+        if data.len() > self.available() {
+            return Err(());
+        }
+
+        for item in data {
+            self.write(*item);
+        }
+        Ok(())
     }
 
     /// Returns the total bytes stored in the buffer.
@@ -66,7 +106,7 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     /// Returns the available space left in the buffer
     /// before writes are ignored.
     pub fn available(&self) -> usize {
-        SIZE-1 - self.len()
+        Self::CAPACITY - self.len()
     }
 
     /// Reads the byte at the front of the buffer.
@@ -82,6 +122,49 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
          Some(value)
     }
 
+    /// Returns the byte at the front of the buffer without removing it.
+    #[inline(always)]
+    pub fn peek(&self) -> Option<T> {
+        self.peek_at(0)
+    }
+
+    /// Returns the byte `index` places from the front of the buffer without removing it,
+    /// or `None` if the buffer isn't that long.
+    pub fn peek_at(&self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let i = (self.tail + index) % SIZE;
+        Some(unsafe { self.buffer[i].assume_init() })
+    }
+
+    /// Returns the contents of the buffer as two slices, in order.
+    /// The second slice is only used when the data wraps around the end of the buffer, and is empty otherwise.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (front, back) = if self.tail <= self.head {
+            (&self.buffer[self.tail..self.head], &self.buffer[..0])
+        } else {
+            (&self.buffer[self.tail..], &self.buffer[..self.head])
+        };
+
+        // SAFETY: everything between the tail and the head has been written
+        unsafe { (assume_init_slice(front), assume_init_slice(back)) }
+    }
+
+    /// Returns an iterator over the contents of the buffer, from front to back, without removing anything.
+    #[inline(always)]
+    pub fn iter(&self) -> Iter<'_, T, SIZE> {
+        Iter { buffer: self, index: 0 }
+    }
+
+    /// Removes every byte from the buffer, returning them in an iterator from front to back.
+    /// Anything left in the iterator when it is dropped is removed anyway.
+    #[inline(always)]
+    pub fn drain(&mut self) -> Drain<'_, T, SIZE> {
+        Drain { buffer: self }
+    }
+
     /// Returns `true` if the buffer contains no bytes.
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
@@ -91,7 +174,7 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     /// Returns `true` if the buffer is at it's maximum capacity, meaning any further writes will be ignored.
     #[inline(always)]
     pub fn is_full(&self) -> bool {
-        self.len() >= Self::MAX_SIZE
+        self.len() >= Self::CAPACITY
     }
 
     /// Clears the buffer
@@ -101,6 +184,12 @@ impl<T: Copy, const SIZE: usize> Buffer<T, SIZE> {
     }
 }
 
+/// Converts a slice of initialized `MaybeUninit<T>` to `&[T]`.
+#[inline(always)]
+unsafe fn assume_init_slice<T>(slice: &[MaybeUninit<T>]) -> &[T] {
+    &*(slice as *const [MaybeUninit<T>] as *const [T])
+}
+
 impl<T: Copy, const SIZE: usize> core::ops::Index<usize> for Buffer<T, SIZE> {
     type Output = T;
 
@@ -123,10 +212,450 @@ impl<T: Copy, const SIZE: usize> core::ops::IndexMut<usize> for Buffer<T, SIZE>
     }
 }
 
-impl<T: Copy, const SIZE: usize> Iterator for Buffer<T, SIZE> {
+/// An iterator over the contents of a [`Buffer`], created by [`Buffer::iter()`].
+pub struct Iter<'a, T: Copy, const SIZE: usize> {
+    buffer: &'a Buffer<T, SIZE>,
+    index: usize,
+}
+
+impl<'a, T: Copy, const SIZE: usize> Iterator for Iter<'a, T, SIZE> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.buffer.len() {
+            return None;
+        }
+
+        let item = &self.buffer[self.index];
+        self.index += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffer.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: Copy, const SIZE: usize> IntoIterator for &'a Buffer<T, SIZE> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, SIZE>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator that removes the contents of a [`Buffer`], created by [`Buffer::drain()`].
+pub struct Drain<'a, T: Copy, const SIZE: usize> {
+    buffer: &'a mut Buffer<T, SIZE>,
+}
+
+impl<'a, T: Copy, const SIZE: usize> Iterator for Drain<'a, T, SIZE> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.read()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffer.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: Copy, const SIZE: usize> Drop for Drain<'a, T, SIZE> {
+    fn drop(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// An iterator that moves out of a [`Buffer`], from front to back.
+pub struct IntoIter<T: Copy, const SIZE: usize> {
+    buffer: Buffer<T, SIZE>,
+}
+
+impl<T: Copy, const SIZE: usize> Iterator for IntoIter<T, SIZE> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.read()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffer.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Copy, const SIZE: usize> IntoIterator for Buffer<T, SIZE> {
+    type Item = T;
+    type IntoIter = IntoIter<T, SIZE>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { buffer: self }
+    }
+}
+
+/// A lock-free queue for passing data from an interrupt to the main loop, or the other way around.
+///
+/// The queue is split into a [`Producer`], which writes to the head, and a [`Consumer`], which reads from the tail.
+/// Each side only ever writes its own index, and the indices are single bytes, which are read and written atomically on AVR,
+/// so neither side has to disable interrupts.
+///
+/// Like [`Buffer`], the queue can hold `SIZE - 1` items, and `SIZE` can be at most 256.
+///
+/// # Example
+/// ```rust,no_run
+/// use atmega::buffer::Queue;
+///
+/// static QUEUE: Queue<u8, 16> = Queue::new();
+///
+/// // In the interrupt
+/// let _ = unsafe { QUEUE.producer() }.enqueue(42);
+///
+/// // In the main loop
+/// while let Some(byte) = unsafe { QUEUE.consumer() }.dequeue() {
+///     println!("{}", byte);
+/// }
+/// ```
+pub struct Queue<T: Copy, const SIZE: usize> {
+    head: UnsafeCell<u8>,
+    tail: UnsafeCell<u8>,
+    buffer: UnsafeCell<[MaybeUninit<T>; SIZE]>,
+}
+
+impl<T: Copy, const SIZE: usize> Queue<T, SIZE> {
+    /// Creates a new empty queue.
+    ///
+    /// # Panics
+    /// Will panic if `SIZE` is larger than 256 or smaller than 2.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        assert!(SIZE <= 256, "Queue SIZE can be at most 256");
+        assert!(SIZE >= 2, "Queue SIZE must be at least 2");
+
+        Queue {
+            head: UnsafeCell::new(0),
+            tail: UnsafeCell::new(0),
+            buffer: UnsafeCell::new(MaybeUninit::uninit_array()),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer halves.
+    #[inline(always)]
+    pub fn split(&mut self) -> (Producer<'_, T, SIZE>, Consumer<'_, T, SIZE>) {
+        (Producer { queue: &*self }, Consumer { queue: &*self })
+    }
+
+    /// Returns the producer half of a shared queue.
+    ///
+    /// # Safety
+    /// There can only be one `Producer` for the queue at a time.
+    /// This is usually done by only producing from one interrupt, or only from the main loop.
+    #[inline(always)]
+    pub unsafe fn producer(&self) -> Producer<'_, T, SIZE> {
+        Producer { queue: self }
+    }
+
+    /// Returns the consumer half of a shared queue.
+    ///
+    /// # Safety
+    /// There can only be one `Consumer` for the queue at a time.
+    /// This is usually done by only consuming from one interrupt, or only from the main loop.
+    #[inline(always)]
+    pub unsafe fn consumer(&self) -> Consumer<'_, T, SIZE> {
+        Consumer { queue: self }
+    }
+
+    /// Returns the total items stored in the queue.
+    pub fn len(&self) -> usize {
+        (SIZE + self.head() as usize - self.tail() as usize) % SIZE
+    }
+
+    /// Returns `true` if the queue contains no items.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.head() == self.tail()
+    }
+
+    /// Returns `true` if the queue is at it's maximum capacity, meaning any further writes will fail.
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        Self::next(self.head()) == self.tail()
+    }
+
+    #[inline(always)]
+    fn head(&self) -> u8 {
+        unsafe { read_volatile(self.head.get()) }
+    }
+
+    #[inline(always)]
+    fn tail(&self) -> u8 {
+        unsafe { read_volatile(self.tail.get()) }
+    }
+
+    #[inline(always)]
+    fn next(index: u8) -> u8 {
+        ((index as usize + 1) % SIZE) as u8
+    }
+}
+
+// SAFETY: the head is only written by the `Producer` and the tail by the `Consumer`,
+// and there can only be one of each at a time.
+unsafe impl<T: Copy + Send, const SIZE: usize> Sync for Queue<T, SIZE> {}
+
+/// The writing half of a [`Queue`].
+pub struct Producer<'a, T: Copy, const SIZE: usize> {
+    queue: &'a Queue<T, SIZE>,
+}
+
+impl<'a, T: Copy, const SIZE: usize> Producer<'a, T, SIZE> {
+    /// Writes an item to the head of the queue.
+    /// Returns the item back if the queue is full.
+    pub fn enqueue(&mut self, value: T) -> Result<(), T> {
+        let head = self.queue.head();
+        let next = Queue::<T, SIZE>::next(head);
+
+        if next == self.queue.tail() {
+            return Err(value);
+        }
+
+        unsafe {
+            write_volatile((self.queue.buffer.get() as *mut MaybeUninit<T>).add(head as usize), MaybeUninit::new(value));
+            // The item has to be written before the consumer can see the new head
+            compiler_fence(Ordering::Release);
+            write_volatile(self.queue.head.get(), next);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the space left in the queue.
+    pub fn available(&self) -> usize {
+        SIZE - 1 - self.queue.len()
+    }
+
+    /// Returns `true` if the queue is full.
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+/// The reading half of a [`Queue`].
+pub struct Consumer<'a, T: Copy, const SIZE: usize> {
+    queue: &'a Queue<T, SIZE>,
+}
+
+impl<'a, T: Copy, const SIZE: usize> Consumer<'a, T, SIZE> {
+    /// Reads the item at the tail of the queue.
+    /// Returns `None` if the queue is empty.
+    pub fn dequeue(&mut self) -> Option<T> {
+        let value = self.peek()?;
+        let tail = self.queue.tail();
+
+        unsafe {
+            // The item has to be read before the producer can overwrite it
+            compiler_fence(Ordering::Release);
+            write_volatile(self.queue.tail.get(), Queue::<T, SIZE>::next(tail));
+        }
+
+        Some(value)
+    }
+
+    /// Returns the item at the tail of the queue without removing it.
+    pub fn peek(&self) -> Option<T> {
+        let tail = self.queue.tail();
+
+        if tail == self.queue.head() {
+            return None;
+        }
+
+        unsafe {
+            // The head has to be read before the item it points past
+            compiler_fence(Ordering::Acquire);
+            let item = read_volatile((self.queue.buffer.get() as *const MaybeUninit<T>).add(tail as usize));
+            Some(item.assume_init())
+        }
+    }
+
+    /// Returns the total items waiting in the queue.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if there is nothing waiting in the queue.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Discards everything waiting in the queue.
+    pub fn clear(&mut self) {
+        let head = self.queue.head();
+        unsafe { write_volatile(self.queue.tail.get(), head) };
+    }
+}
+
+impl<'a, T: Copy, const SIZE: usize> Iterator for Consumer<'a, T, SIZE> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.read()
+        self.dequeue()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills a buffer so the data wraps around the end of the storage.
+    fn wrapped() -> Buffer<u8, 4> {
+        let mut buf = Buffer::new();
+        buf.write(0);
+        buf.write(0);
+        buf.read();
+        buf.read();
+        buf.write(1);
+        buf.write(2);
+        buf.write(3);
+        buf
+    }
+
+    #[test]
+    fn write_read() {
+        let mut buf: Buffer<u8, 4> = Buffer::new();
+        assert!(buf.is_empty());
+        buf.write(1);
+        buf.write(2);
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf.read(), Some(1));
+        assert_eq!(buf.read(), Some(2));
+        assert_eq!(buf.read(), None);
+    }
+
+    #[test]
+    fn full() {
+        let mut buf: Buffer<u8, 4> = Buffer::new();
+        assert_eq!(Buffer::<u8, 4>::CAPACITY, 3);
+        for i in 0..3 {
+            assert!(!buf.is_full());
+            assert_eq!(buf.try_write(i), Ok(()));
+        }
+        assert!(buf.is_full());
+        assert_eq!(buf.available(), 0);
+        assert_eq!(buf.try_write(3), Err(3));
+
+        buf.write(3);
+        assert_eq!(buf.len(), 3);
+        assert_eq!(buf.peek_at(2), Some(2));
+    }
+
+    #[test]
+    fn overwrite() {
+        let mut buf: Buffer<u8, 4> = Buffer::new();
+        assert_eq!(buf.overwrite(1), None);
+        buf.write(2);
+        buf.write(3);
+        assert_eq!(buf.overwrite(4), Some(1));
+        assert_eq!(buf.overwrite(5), Some(2));
+        assert_eq!(buf.read(), Some(3));
+        assert_eq!(buf.read(), Some(4));
+        assert_eq!(buf.read(), Some(5));
+    }
+
+    #[test]
+    fn peek() {
+        let mut buf = wrapped();
+        assert_eq!(buf.peek(), Some(1));
+        assert_eq!(buf.peek_at(1), Some(2));
+        assert_eq!(buf.peek_at(2), Some(3));
+        assert_eq!(buf.peek_at(3), None);
+        assert_eq!(buf.len(), 3);
+
+        buf.clear();
+        assert_eq!(buf.peek(), None);
+    }
+
+    #[test]
+    fn slices() {
+        let buf: Buffer<u8, 4> = Buffer::copy_from_slice(&[1, 2]);
+        assert_eq!(buf.as_slices(), (&[1, 2][..], &[][..]));
+
+        let buf = wrapped();
+        assert_eq!(buf.as_slices(), (&[1, 2][..], &[3][..]));
+
+        let buf: Buffer<u8, 4> = Buffer::new();
+        assert_eq!(buf.as_slices(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn iter() {
+        let buf = wrapped();
+        let mut iter = buf.iter();
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), None);
+
+        // Iterating doesn't remove anything
+        assert_eq!(buf.len(), 3);
+        assert_eq!((&buf).into_iter().sum::<u8>(), 6);
+        assert_eq!(buf.into_iter().sum::<u8>(), 6);
+    }
+
+    #[test]
+    fn extend() {
+        let mut buf: Buffer<u8, 4> = Buffer::new();
+        assert_eq!(buf.extend_from_slice(&[1, 2]), Ok(()));
+        assert_eq!(buf.extend_from_slice(&[3, 4]), Err(()));
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf.extend_from_slice(&[3]), Ok(()));
+        assert!(buf.is_full());
+    }
+
+    #[test]
+    fn drain() {
+        let mut buf = wrapped();
+        let mut drain = buf.drain();
+        assert_eq!(drain.next(), Some(1));
+        drop(drain);
+        assert!(buf.is_empty());
+
+        let mut buf = wrapped();
+        assert_eq!(buf.drain().fold(0, |acc, x| acc * 10 + x), 123);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_from_slice_too_large() {
+        let _: Buffer<u8, 4> = Buffer::copy_from_slice(&[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn queue() {
+        let mut queue: Queue<u8, 4> = Queue::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        for i in 0..3 {
+            assert_eq!(producer.enqueue(i), Ok(()));
+        }
+        assert!(producer.is_full());
+        assert_eq!(producer.enqueue(3), Err(3));
+
+        assert_eq!(consumer.peek(), Some(0));
+        assert_eq!(consumer.dequeue(), Some(0));
+        assert_eq!(producer.enqueue(3), Ok(()));
+        assert_eq!(consumer.len(), 3);
+        assert_eq!(consumer.by_ref().fold(0, |acc, x| acc * 10 + x), 123);
+        assert!(consumer.is_empty());
+
+        producer.enqueue(4).unwrap();
+        consumer.clear();
+        assert_eq!(consumer.dequeue(), None);
     }
 }
@@ -0,0 +1,607 @@
+//! Fixed-capacity collections that don't need an allocator.
+//!
+//! [`ArrayVec`] and [`ArrayString`] store their contents inline, so they can live on the stack or in a `static`.
+//! Lengths are stored in a single byte to save RAM, which limits both to 255 items.
+//!
+//! # Example
+//! ```rust,no_run
+//! use core::fmt::Write;
+//! use atmega::collections::{ ArrayString, ArrayVec };
+//!
+//! let mut readings: ArrayVec<u16, 8> = ArrayVec::new();
+//! readings.push(analog_read(Pin::A0)).unwrap();
+//!
+//! let mut message: ArrayString<32> = ArrayString::new();
+//! write!(message, "A0: {}", readings[0]).unwrap();
+//! println!("{}", message);
+//! ```
+
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{ Deref, DerefMut };
+use core::ptr;
+
+/// A vector with a fixed capacity of `N` items, stored inline.
+///
+/// # Panics
+/// Creating an `ArrayVec` with `N` larger than 255 will panic.
+pub struct ArrayVec<T, const N: usize> {
+    len: u8,
+    data: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    /// Creates a new empty `ArrayVec`.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        assert!(N <= u8::MAX as usize, "ArrayVec capacity can be at most 255");
+
+        ArrayVec {
+            len: 0,
+            data: MaybeUninit::uninit_array(),
+        }
+    }
+
+    /// Returns the number of items in the vector.
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if the vector contains no items.
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the vector can't hold any more items.
+    #[inline(always)]
+    pub const fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Returns the maximum number of items the vector can hold.
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the space left in the vector.
+    #[inline(always)]
+    pub const fn remaining_capacity(&self) -> usize {
+        N - self.len()
+    }
+
+    /// Adds an item to the end of the vector.
+    /// Returns the item back if the vector is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.data[self.len()].write(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last item from the vector and returns it, or `None` if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        Some(unsafe { self.data[self.len()].assume_init_read() })
+    }
+
+    /// Inserts an item at `index`, shifting everything after it to the right.
+    /// Returns the item back if the vector is full.
+    ///
+    /// # Panics
+    /// Will panic if `index` is greater than the length.
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        if index > self.len() {
+            panic!("Insertion index {} out of range for ArrayVec of length {}.", index, self.len());
+        }
+        if self.is_full() {
+            return Err(value);
+        }
+
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.len() - index);
+            ptr::write(p, value);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the item at `index`, shifting everything after it to the left.
+    ///
+    /// # Panics
+    /// Will panic if `index` is out of range.
+    pub fn remove(&mut self, index: usize) -> T {
+        if index >= self.len() {
+            panic!("Index out of range! Index of {} into ArrayVec of length {}.", index, self.len());
+        }
+
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let value = ptr::read(p);
+            ptr::copy(p.add(1), p, self.len() - index - 1);
+            self.len -= 1;
+            value
+        }
+    }
+
+    /// Removes and returns the item at `index`, replacing it with the last item.
+    /// Faster than [`remove()`](ArrayVec::remove), but doesn't keep the order.
+    ///
+    /// # Panics
+    /// Will panic if `index` is out of range.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        if index >= self.len() {
+            panic!("Index out of range! Index of {} into ArrayVec of length {}.", index, self.len());
+        }
+
+        let last = self.len() - 1;
+        self.as_mut_slice().swap(index, last);
+        // The vector can't be empty, since `index` is in range
+        self.pop().unwrap()
+    }
+
+    /// Shortens the vector to `len` items, dropping the rest.
+    /// Does nothing if the vector is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len() > len {
+            self.pop();
+        }
+    }
+
+    /// Removes every item from the vector.
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the contents of the vector as a slice.
+    #[inline(always)]
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.data.as_ptr() as *const T, self.len()) }
+    }
+
+    /// Returns the contents of the vector as a mutable slice.
+    #[inline(always)]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+
+    #[inline(always)]
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.data.as_mut_ptr() as *mut T
+    }
+}
+
+impl<T: Clone, const N: usize> ArrayVec<T, N> {
+    /// Clones every item in the slice onto the end of the vector.
+    /// Returns `Err(())` without adding anything if there isn't enough space for the whole slice.
+    pub fn extend_from_slice(&mut self, items: &[T]) -> Result<(), ()> {
+        if items.len() > self.remaining_capacity() {
+            return Err(());
+        }
+
+        for item in items {
+            let _ = self.push(item.clone());
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut new = Self::new();
+        // The new vector has the same capacity, so this can't fail
+        let _ = new.extend_from_slice(self);
+        new
+    }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<ArrayVec<T, M>> for ArrayVec<T, N> {
+    fn eq(&self, other: &ArrayVec<T, M>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T]> for ArrayVec<T, N> {
+    fn eq(&self, other: &[T]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut ArrayVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// A UTF-8 string with a fixed capacity of `N` bytes, stored inline.
+///
+/// Implements [`fmt::Write`], so it can be formatted into with `write!`.
+///
+/// # Panics
+/// Creating an `ArrayString` with `N` larger than 255 will panic.
+#[derive(Clone, Copy)]
+pub struct ArrayString<const N: usize> {
+    len: u8,
+    data: [u8; N],
+}
+
+impl<const N: usize> ArrayString<N> {
+    /// Creates a new empty `ArrayString`.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        assert!(N <= u8::MAX as usize, "ArrayString capacity can be at most 255");
+
+        ArrayString {
+            len: 0,
+            data: [0; N],
+        }
+    }
+
+    /// Returns the length of the string in bytes.
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if the string is empty.
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum length of the string in bytes.
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the bytes left before the string is full.
+    #[inline(always)]
+    pub const fn remaining_capacity(&self) -> usize {
+        N - self.len()
+    }
+
+    /// Adds a character to the end of the string.
+    /// Returns `Err(())` if there isn't enough space for it.
+    pub fn push(&mut self, c: char) -> Result<(), ()> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Adds a string to the end of the string.
+    /// Returns `Err(())` without adding anything if there isn't enough space for the whole string.
+    pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
+        if s.len() > self.remaining_capacity() {
+            return Err(());
+        }
+
+        let start = self.len();
+        self.data[start..start + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len() as u8;
+        Ok(())
+    }
+
+    /// Removes the last character from the string and returns it, or `None` if it is empty.
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        self.len -= c.len_utf8() as u8;
+        Some(c)
+    }
+
+    /// Shortens the string to `len` bytes.
+    /// Does nothing if the string is already shorter.
+    ///
+    /// # Panics
+    /// Will panic if `len` is not on a character boundary.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            if !self.as_str().is_char_boundary(len) {
+                panic!("Truncation length {} is not on a character boundary.", len);
+            }
+            self.len = len as u8;
+        }
+    }
+
+    /// Empties the string.
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the contents as a `&str`.
+    #[inline(always)]
+    pub fn as_str(&self) -> &str {
+        // SAFETY: only whole `&str`s are ever written, and truncation keeps to character boundaries
+        unsafe { core::str::from_utf8_unchecked(self.as_bytes()) }
+    }
+
+    /// Returns the contents as bytes, for sending over `Serial` or `wire`.
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len()]
+    }
+}
+
+impl<const N: usize> Deref for ArrayString<N> {
+    type Target = str;
+
+    #[inline(always)]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Default for ArrayString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TryFrom<&str> for ArrayString<N> {
+    type Error = ();
+
+    /// Creates an `ArrayString` containing the given string.
+    /// Returns `Err(())` if the string is longer than the capacity.
+    fn try_from(s: &str) -> Result<Self, ()> {
+        let mut new = Self::new();
+        new.push_str(s)?;
+        Ok(new)
+    }
+}
+
+impl<const N: usize> fmt::Write for ArrayString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const N: usize> fmt::Display for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Debug for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<ArrayString<M>> for ArrayString<N> {
+    fn eq(&self, other: &ArrayString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> PartialEq<str> for ArrayString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for ArrayString<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::fmt::Write;
+
+    /// Counts how many times it has been dropped.
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut vec: ArrayVec<u8, 4> = ArrayVec::new();
+        assert!(vec.is_empty());
+        assert_eq!(vec.pop(), None);
+        vec.push(1).unwrap();
+        vec.push(2).unwrap();
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.pop(), Some(2));
+        assert_eq!(vec.pop(), Some(1));
+        assert_eq!(vec.pop(), None);
+    }
+
+    #[test]
+    fn full() {
+        let mut vec: ArrayVec<u8, 3> = ArrayVec::new();
+        vec.extend_from_slice(&[1, 2, 3]).unwrap();
+        assert!(vec.is_full());
+        assert_eq!(vec.remaining_capacity(), 0);
+        assert_eq!(vec.push(4), Err(4));
+        assert_eq!(vec.insert(0, 4), Err(4));
+        assert_eq!(vec.extend_from_slice(&[4]), Err(()));
+        assert_eq!(vec, [1, 2, 3][..]);
+
+        vec.pop();
+        assert_eq!(vec.extend_from_slice(&[4, 5]), Err(()));
+        assert_eq!(vec, [1, 2][..]);
+    }
+
+    #[test]
+    fn insert() {
+        let mut vec: ArrayVec<u8, 5> = ArrayVec::new();
+        vec.extend_from_slice(&[2, 4]).unwrap();
+        vec.insert(0, 1).unwrap();
+        vec.insert(2, 3).unwrap();
+        vec.insert(4, 5).unwrap();
+        assert_eq!(vec, [1, 2, 3, 4, 5][..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_out_of_range() {
+        let mut vec: ArrayVec<u8, 4> = ArrayVec::new();
+        vec.push(1).unwrap();
+        let _ = vec.insert(2, 2);
+    }
+
+    #[test]
+    fn remove() {
+        let mut vec: ArrayVec<u8, 5> = ArrayVec::new();
+        vec.extend_from_slice(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(vec.remove(0), 1);
+        assert_eq!(vec.remove(1), 3);
+        assert_eq!(vec.remove(2), 5);
+        assert_eq!(vec, [2, 4][..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_out_of_range() {
+        let mut vec: ArrayVec<u8, 4> = ArrayVec::new();
+        vec.push(1).unwrap();
+        vec.remove(1);
+    }
+
+    #[test]
+    fn swap_remove() {
+        let mut vec: ArrayVec<u8, 4> = ArrayVec::new();
+        vec.extend_from_slice(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(vec.swap_remove(0), 1);
+        assert_eq!(vec, [4, 2, 3][..]);
+        assert_eq!(vec.swap_remove(2), 3);
+        assert_eq!(vec, [4, 2][..]);
+        assert_eq!(vec.swap_remove(1), 2);
+        assert_eq!(vec.swap_remove(0), 4);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn drops() {
+        let count = Cell::new(0);
+        let mut vec: ArrayVec<DropCounter, 4> = ArrayVec::new();
+        for _ in 0..4 {
+            assert!(vec.push(DropCounter(&count)).is_ok());
+        }
+
+        // A rejected item is handed back rather than dropped
+        let rejected = vec.push(DropCounter(&count));
+        assert_eq!(count.get(), 0);
+        drop(rejected);
+        assert_eq!(count.get(), 1);
+
+        drop(vec.remove(1));
+        drop(vec.swap_remove(0));
+        assert_eq!(count.get(), 3);
+
+        vec.truncate(1);
+        assert_eq!(count.get(), 4);
+
+        drop(vec);
+        assert_eq!(count.get(), 5);
+    }
+
+    #[test]
+    fn string_push() {
+        let mut s: ArrayString<4> = ArrayString::new();
+        s.push_str("ab").unwrap();
+        s.push('c').unwrap();
+        assert_eq!(s, "abc");
+        assert_eq!(s.push('é'), Err(()));
+        assert_eq!(s.push_str("de"), Err(()));
+        assert_eq!(s, "abc");
+        s.push('d').unwrap();
+        assert_eq!(s.remaining_capacity(), 0);
+    }
+
+    #[test]
+    fn string_pop_multibyte() {
+        let mut s: ArrayString<16> = ArrayString::try_from("aé€🦀").unwrap();
+        assert_eq!(s.len(), 10);
+        assert_eq!(s.pop(), Some('🦀'));
+        assert_eq!(s.len(), 6);
+        assert_eq!(s.pop(), Some('€'));
+        assert_eq!(s.pop(), Some('é'));
+        assert_eq!(s, "a");
+        assert_eq!(s.pop(), Some('a'));
+        assert_eq!(s.pop(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn string_truncate_mid_char() {
+        let mut s: ArrayString<4> = ArrayString::try_from("é").unwrap();
+        s.truncate(1);
+    }
+
+    #[test]
+    fn string_write() {
+        let mut s: ArrayString<8> = ArrayString::new();
+        write!(s, "{}-{}", 12, 34).unwrap();
+        assert_eq!(s, "12-34");
+        assert_eq!(write!(s, "{}", 5678), Err(fmt::Error));
+        assert_eq!(s.len(), 5);
+    }
+}
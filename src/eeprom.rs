@@ -0,0 +1,278 @@
+//! Reading and writing the 1KiB of on-chip EEPROM, which keeps its contents across resets and power cycles.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::eeprom;
+//!
+//! let boots = eeprom::read(0);
+//! eeprom::update(0, boots.wrapping_add(1));
+//! ```
+
+use core::arch::asm;
+use core::mem::{ size_of, MaybeUninit };
+use core::slice;
+use crate::interrupts::{ self, State };
+use crate::registers::{ EECR, EEDR, EEARL, EEARH, Register };
+
+/// Total size of the EEPROM in bytes.
+pub const SIZE: usize = 1024;
+
+/// Returns `true` if the EEPROM is not busy with a previous write.
+#[inline(always)]
+pub fn is_ready() -> bool {
+    unsafe { !EECR::EEPE.read_bit() }
+}
+
+/// Blocks until the previous write has finished.
+/// A write takes about 3.3ms to complete.
+#[inline(always)]
+fn wait_ready() {
+    while !is_ready() {}
+}
+
+/// Loads the given address into `EEAR`.
+#[inline(always)]
+unsafe fn set_address(addr: u16) {
+    EEARH::write((addr >> 8) as u8);
+    EEARL::write(addr as u8);
+}
+
+/// Makes sure `len` bytes starting at `addr` are inside of the EEPROM.
+///
+/// # Panics
+/// Panics if the range extends past the end of the EEPROM.
+#[inline(always)]
+fn check_range(addr: u16, len: usize) {
+    assert!(addr as usize + len <= SIZE, "EEPROM access out of range");
+}
+
+/// Reads the byte stored at the given address.
+///
+/// # Panics
+/// Will panic if the address is outside of the EEPROM.
+pub fn read(addr: u16) -> u8 {
+    check_range(addr, 1);
+    wait_ready();
+
+    unsafe {
+        set_address(addr);
+        // Start the read. The CPU is halted for 4 cycles, after which the data is available in EEDR.
+        EECR::EERE.set();
+        EEDR::read()
+    }
+}
+
+/// Writes a byte to the given address, regardless of what is already stored there.
+///
+/// Prefer [`update`] where possible, since each cell only survives around 100,000 writes.
+///
+/// # Panics
+/// Will panic if the address is outside of the EEPROM.
+pub fn write(addr: u16, value: u8) {
+    check_range(addr, 1);
+    wait_ready();
+
+    // EEPE has to be set within four cycles of EEMPE,
+    // so an interrupt in between would cause the write to be silently dropped.
+    interrupts::without(State::Restore, || unsafe {
+        set_address(addr);
+        EEDR::write(value);
+
+        // Atomic erase and write
+        EECR::EEPM0.clear();
+        EECR::EEPM1.clear();
+
+        asm!(
+            // Set EEMPE
+            "sbi 0x1F, 2",
+            // Set EEPE
+            "sbi 0x1F, 1",
+            options(nostack),
+        );
+    });
+}
+
+/// Writes a byte to the given address only if it differs from the value already stored there.
+/// This saves both time and EEPROM wear.
+///
+/// # Panics
+/// Will panic if the address is outside of the EEPROM.
+pub fn update(addr: u16, value: u8) {
+    if read(addr) != value {
+        write(addr, value);
+    }
+}
+
+/// Fills `out` with the bytes stored starting at the given address.
+///
+/// # Panics
+/// Will panic if the range extends past the end of the EEPROM.
+pub fn read_slice(addr: u16, out: &mut [u8]) {
+    check_range(addr, out.len());
+
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = read(addr + i as u16);
+    }
+}
+
+/// Writes every byte in `data` starting at the given address.
+///
+/// # Panics
+/// Will panic if the range extends past the end of the EEPROM.
+pub fn write_slice(addr: u16, data: &[u8]) {
+    check_range(addr, data.len());
+
+    for (i, byte) in data.iter().enumerate() {
+        write(addr + i as u16, *byte);
+    }
+}
+
+/// Writes the bytes in `data` starting at the given address, skipping any that are unchanged.
+///
+/// # Panics
+/// Will panic if the range extends past the end of the EEPROM.
+pub fn update_slice(addr: u16, data: &[u8]) {
+    check_range(addr, data.len());
+
+    for (i, byte) in data.iter().enumerate() {
+        update(addr + i as u16, *byte);
+    }
+}
+
+/// Reads a value of type `T` stored at the given address.
+///
+/// # Safety
+/// Caller must make sure that the bytes stored at the address are a valid `T`.
+/// Erased EEPROM reads as `0xFF`, which is not a valid value for many types.
+///
+/// # Panics
+/// Will panic if the value extends past the end of the EEPROM.
+pub unsafe fn read_value<T: Sized>(addr: u16) -> T {
+    let mut buf = MaybeUninit::<T>::uninit();
+    read_slice(addr, slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, size_of::<T>()));
+    buf.assume_init()
+}
+
+/// Writes the given value starting at the given address.
+///
+/// # Panics
+/// Will panic if the value extends past the end of the EEPROM.
+pub fn write_value<T: Sized>(addr: u16, value: &T) {
+    let bytes = unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    write_slice(addr, bytes);
+}
+
+/// Writes the given value starting at the given address, skipping any bytes that are unchanged.
+///
+/// # Panics
+/// Will panic if the value extends past the end of the EEPROM.
+pub fn update_value<T: Sized>(addr: u16, value: &T) {
+    let bytes = unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    update_slice(addr, bytes);
+}
+
+/// A wrapper for an address to a variable stored in EEPROM.
+/// Can be used to load and store a typed value.
+/// Should be instantiated with the `eeprom!` macro.
+///
+/// # Example
+/// ```rust,no_run
+/// eeprom! {
+///     eeprom BOOTS: u16 = 0;
+/// }
+///
+/// fn count_boot() {
+///     BOOTS.update(BOOTS.load().wrapping_add(1));
+/// }
+/// ```
+///
+/// # Safety
+/// Caller must ensure that the internal address points to a variable
+/// stored in the EEPROM address space. This is assured for
+/// variables with the `#[link_section = ".eeprom"]` attribute.
+pub struct EepromCell<T: Sized>(*const T);
+
+impl<T: Sized> EepromCell<T> {
+    /// Creates a new instance of `EepromCell` from the address of a value in EEPROM.
+    ///
+    /// # Safety
+    /// Caller must ensure the address is within the EEPROM address space.
+    /// This is assured for values with the `#[link_section = ".eeprom"]` attribute.
+    pub const fn new(inner: *const T) -> EepromCell<T> {
+        EepromCell(inner)
+    }
+
+    /// Creates a new instance of `EepromCell` at a fixed EEPROM address.
+    ///
+    /// # Safety
+    /// Caller must ensure the value will fit inside the EEPROM,
+    /// and that it does not overlap any other values.
+    pub const unsafe fn at(address: u16) -> EepromCell<T> {
+        EepromCell(address as usize as *const T)
+    }
+
+    /// The address of the value inside the EEPROM.
+    #[inline(always)]
+    pub fn address(&self) -> u16 {
+        // The linker places `.eeprom` at 0x810000, so the upper bits are truncated away.
+        self.0 as usize as u16
+    }
+
+    /// Loads the value stored in EEPROM.
+    ///
+    /// The value is only guaranteed to be valid if it was uploaded with the program,
+    /// or has been written with [`store`](Self::store) or [`update`](Self::update).
+    pub fn load(&self) -> T {
+        unsafe { read_value(self.address()) }
+    }
+
+    /// Writes the given value into EEPROM.
+    pub fn store(&self, value: T) {
+        write_value(self.address(), &value);
+    }
+
+    /// Writes the given value into EEPROM, skipping any bytes that are unchanged.
+    pub fn update(&self, value: T) {
+        update_value(self.address(), &value);
+    }
+}
+
+unsafe impl<T: Sized> Send for EepromCell<T> {}
+unsafe impl<T: Sized> Sync for EepromCell<T> {}
+
+/// Allows for the storage of statics in EEPROM.
+///
+/// Reserves space for the given expression in the `.eeprom` section and replaces type `T` with `EepromCell<T>`.
+/// The initial values are only present if the generated `.eep` file is uploaded alongside the program.
+///
+/// # Example
+/// ```rust,no_run
+/// eeprom! {
+///     eeprom BRIGHTNESS: u8 = 128;
+///     eeprom CALIBRATION: [i16; 3] = [0, 0, 0];
+/// }
+///
+/// fn brightness() -> u8 {
+///     BRIGHTNESS.load()
+/// }
+/// ```
+#[macro_export]
+macro_rules! eeprom {
+    {
+        $(
+            $(#[$attr:meta])*
+            $vis:vis eeprom $name:ident: $ty:ty = $value:expr;
+        )*
+    } => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::eeprom::EepromCell<$ty> = {
+                #[link_section = ".eeprom"]
+                $vis static $name: $ty = $value;
+                $crate::eeprom::EepromCell::new(
+                    ::core::ptr::addr_of!($name)
+                )
+            };
+        )*
+    }
+}
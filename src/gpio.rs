@@ -0,0 +1,264 @@
+//! Zero-cost pins that track their mode in the type system.
+//!
+//! Unlike the Arduino-style functions in [`wiring`](crate::wiring), the port and bit of each pin is known at compile time,
+//! and writing to an input or reading from an output is a compile error.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::gpio::Pins;
+//!
+//! let pins = Pins::take().unwrap();
+//! let mut led = pins.d13.into_output();
+//! let button = pins.d2.into_pull_up_input();
+//!
+//! loop {
+//!     if button.is_low() {
+//!         led.set_high();
+//!     } else {
+//!         led.set_low();
+//!     }
+//! }
+//! ```
+
+use core::marker::PhantomData;
+use crate::bits;
+use crate::interrupts::{ self, State };
+use crate::registers::{ Register, PINB, PINC, PIND, DDRB, DDRC, DDRD, PORTB, PORTC, PORTD };
+use crate::volatile::Volatile;
+
+/// Type state for a pin configured as an input.
+pub struct Input<PULL> {
+    _pull: PhantomData<PULL>,
+}
+
+/// Type state for an input without the internal pull-up resistor.
+pub struct Floating;
+
+/// Type state for an input with the internal pull-up resistor enabled.
+pub struct PullUp;
+
+/// Type state for a pin configured as an output.
+pub struct Output;
+
+/// A single pin in the given mode.
+/// `PORT` is one of `'B'`, `'C'`, or `'D'`, and `BIT` is the offset of the pin in that port.
+pub struct Gpio<MODE, const PORT: char, const BIT: u8> {
+    _mode: PhantomData<MODE>,
+}
+
+impl<MODE, const PORT: char, const BIT: u8> Gpio<MODE, PORT, BIT> {
+    const PINX: *mut u8 = match PORT {
+        'B' => PINB::ADDR,
+        'C' => PINC::ADDR,
+        _   => PIND::ADDR,
+    };
+
+    const DDRX: *mut u8 = match PORT {
+        'B' => DDRB::ADDR,
+        'C' => DDRC::ADDR,
+        _   => DDRD::ADDR,
+    };
+
+    const PORTX: *mut u8 = match PORT {
+        'B' => PORTB::ADDR,
+        'C' => PORTC::ADDR,
+        _   => PORTD::ADDR,
+    };
+
+    #[inline(always)]
+    const fn new() -> Self {
+        Gpio { _mode: PhantomData }
+    }
+
+    /// Configures the pin as an output.
+    #[inline(always)]
+    pub fn into_output(self) -> Gpio<Output, PORT, BIT> {
+        unsafe { bits::operate(Self::DDRX, |val| bits::set(val, BIT)); }
+        Gpio::new()
+    }
+
+    /// Configures the pin as an input without the internal pull-up.
+    #[inline(always)]
+    pub fn into_floating_input(self) -> Gpio<Input<Floating>, PORT, BIT> {
+        unsafe {
+            bits::operate(Self::DDRX, |val| bits::clear(val, BIT));
+            bits::operate(Self::PORTX, |val| bits::clear(val, BIT));
+        }
+        Gpio::new()
+    }
+
+    /// Configures the pin as an input with the internal pull-up enabled.
+    #[inline(always)]
+    pub fn into_pull_up_input(self) -> Gpio<Input<PullUp>, PORT, BIT> {
+        unsafe {
+            bits::operate(Self::DDRX, |val| bits::clear(val, BIT));
+            bits::operate(Self::PORTX, |val| bits::set(val, BIT));
+        }
+        Gpio::new()
+    }
+}
+
+impl<const PORT: char, const BIT: u8> Gpio<Output, PORT, BIT> {
+    /// Drives the pin HIGH.
+    #[inline(always)]
+    pub fn set_high(&mut self) {
+        unsafe { bits::operate(Self::PORTX, |val| bits::set(val, BIT)); }
+    }
+
+    /// Drives the pin LOW.
+    #[inline(always)]
+    pub fn set_low(&mut self) {
+        unsafe { bits::operate(Self::PORTX, |val| bits::clear(val, BIT)); }
+    }
+
+    /// Drives the pin HIGH if `true`, LOW if `false`.
+    #[inline(always)]
+    pub fn set_value(&mut self, value: bool) {
+        unsafe { bits::operate(Self::PORTX, |val| bits::set_value(val, BIT, value)); }
+    }
+
+    /// Toggles the output of the pin.
+    #[inline(always)]
+    pub fn toggle(&mut self) {
+        // Writing a 1 to PINx toggles the matching bit in PORTx in a single write
+        unsafe { core::ptr::write_volatile(Self::PINX, 1 << BIT); }
+    }
+
+    /// Returns `true` if the pin is being driven HIGH.
+    #[inline(always)]
+    pub fn is_set_high(&self) -> bool {
+        unsafe { bits::read(core::ptr::read_volatile(Self::PORTX), BIT) }
+    }
+
+    /// Returns `true` if the pin is being driven LOW.
+    #[inline(always)]
+    pub fn is_set_low(&self) -> bool {
+        !self.is_set_high()
+    }
+}
+
+impl<PULL, const PORT: char, const BIT: u8> Gpio<Input<PULL>, PORT, BIT> {
+    /// Returns `true` if the voltage on the pin is HIGH.
+    #[inline(always)]
+    pub fn is_high(&self) -> bool {
+        unsafe { bits::read(core::ptr::read_volatile(Self::PINX), BIT) }
+    }
+
+    /// Returns `true` if the voltage on the pin is LOW.
+    #[inline(always)]
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+}
+
+#[allow(missing_docs)]
+pub type D0<MODE> = Gpio<MODE, 'D', 0>;
+#[allow(missing_docs)]
+pub type D1<MODE> = Gpio<MODE, 'D', 1>;
+#[allow(missing_docs)]
+pub type D2<MODE> = Gpio<MODE, 'D', 2>;
+#[allow(missing_docs)]
+pub type D3<MODE> = Gpio<MODE, 'D', 3>;
+#[allow(missing_docs)]
+pub type D4<MODE> = Gpio<MODE, 'D', 4>;
+#[allow(missing_docs)]
+pub type D5<MODE> = Gpio<MODE, 'D', 5>;
+#[allow(missing_docs)]
+pub type D6<MODE> = Gpio<MODE, 'D', 6>;
+#[allow(missing_docs)]
+pub type D7<MODE> = Gpio<MODE, 'D', 7>;
+#[allow(missing_docs)]
+pub type D8<MODE> = Gpio<MODE, 'B', 0>;
+#[allow(missing_docs)]
+pub type D9<MODE> = Gpio<MODE, 'B', 1>;
+#[allow(missing_docs)]
+pub type D10<MODE> = Gpio<MODE, 'B', 2>;
+#[allow(missing_docs)]
+pub type D11<MODE> = Gpio<MODE, 'B', 3>;
+#[allow(missing_docs)]
+pub type D12<MODE> = Gpio<MODE, 'B', 4>;
+#[allow(missing_docs)]
+pub type D13<MODE> = Gpio<MODE, 'B', 5>;
+#[allow(missing_docs)]
+pub type A0<MODE> = Gpio<MODE, 'C', 0>;
+#[allow(missing_docs)]
+pub type A1<MODE> = Gpio<MODE, 'C', 1>;
+#[allow(missing_docs)]
+pub type A2<MODE> = Gpio<MODE, 'C', 2>;
+#[allow(missing_docs)]
+pub type A3<MODE> = Gpio<MODE, 'C', 3>;
+#[allow(missing_docs)]
+pub type A4<MODE> = Gpio<MODE, 'C', 4>;
+#[allow(missing_docs)]
+pub type A5<MODE> = Gpio<MODE, 'C', 5>;
+
+static TAKEN: Volatile<bool> = Volatile::new(false);
+
+/// Every pin on the board, each in the floating input mode they start in after reset.
+#[allow(missing_docs)]
+pub struct Pins {
+    pub d0: D0<Input<Floating>>,
+    pub d1: D1<Input<Floating>>,
+    pub d2: D2<Input<Floating>>,
+    pub d3: D3<Input<Floating>>,
+    pub d4: D4<Input<Floating>>,
+    pub d5: D5<Input<Floating>>,
+    pub d6: D6<Input<Floating>>,
+    pub d7: D7<Input<Floating>>,
+    pub d8: D8<Input<Floating>>,
+    pub d9: D9<Input<Floating>>,
+    pub d10: D10<Input<Floating>>,
+    pub d11: D11<Input<Floating>>,
+    pub d12: D12<Input<Floating>>,
+    pub d13: D13<Input<Floating>>,
+    pub a0: A0<Input<Floating>>,
+    pub a1: A1<Input<Floating>>,
+    pub a2: A2<Input<Floating>>,
+    pub a3: A3<Input<Floating>>,
+    pub a4: A4<Input<Floating>>,
+    pub a5: A5<Input<Floating>>,
+}
+
+impl Pins {
+    /// Returns the pins the first time it is called, and `None` after that.
+    pub fn take() -> Option<Pins> {
+        interrupts::without(State::Restore, || {
+            if TAKEN.read() {
+                None
+            } else {
+                TAKEN.write(true);
+                Some(unsafe { Pins::steal() })
+            }
+        })
+    }
+
+    /// Returns the pins regardless of whether they have already been taken.
+    ///
+    /// # Safety
+    /// Caller must make sure that no two handles to the same pin are used at the same time,
+    /// including through the functions in [`wiring`](crate::wiring).
+    pub unsafe fn steal() -> Pins {
+        Pins {
+            d0: Gpio::new(),
+            d1: Gpio::new(),
+            d2: Gpio::new(),
+            d3: Gpio::new(),
+            d4: Gpio::new(),
+            d5: Gpio::new(),
+            d6: Gpio::new(),
+            d7: Gpio::new(),
+            d8: Gpio::new(),
+            d9: Gpio::new(),
+            d10: Gpio::new(),
+            d11: Gpio::new(),
+            d12: Gpio::new(),
+            d13: Gpio::new(),
+            a0: Gpio::new(),
+            a1: Gpio::new(),
+            a2: Gpio::new(),
+            a3: Gpio::new(),
+            a4: Gpio::new(),
+            a5: Gpio::new(),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Implementations of the [`embedded-hal`](https://docs.rs/embedded-hal/1.0.0) and [`embedded-io`](https://docs.rs/embedded-io/0.6.1) traits,
+//! allowing drivers from the wider embedded Rust ecosystem to be used with this crate.
+//!
+//! - Digital pins are provided by the [`gpio`](crate::gpio) pins.
+//! - PWM is provided by the `D3`, `D5`, `D6`, `D9`, `D10`, and `D11` output pins.
+//! - Delays are provided by [`Delay`].
+//! - I2C is provided by [`I2c`], which uses the [`wire`](crate::libraries::wire) library.
+//! - Serial reads and writes are provided by [`Serial`].
+
+use core::convert::Infallible;
+use embedded_hal::{ delay, digital, pwm };
+use crate::constants::CPU_FREQUENCY;
+use crate::gpio::{ self, Gpio, Input, Output };
+use crate::serial::{ self, Serial };
+use crate::timing::{ delay_cycles, delay_micros, delay as delay_millis };
+use crate::wiring::{ analog_write, Pin };
+
+#[cfg(any(feature = "twowire", doc))]
+use embedded_hal::i2c::{ self, Operation, NoAcknowledgeSource };
+#[cfg(any(feature = "twowire", doc))]
+use crate::libraries::wire::{ self, WriteError, TWI_BUFFER_LENGTH };
+#[cfg(any(feature = "twowire", doc))]
+use crate::buffer::Buffer;
+
+impl<MODE, const PORT: char, const BIT: u8> digital::ErrorType for Gpio<MODE, PORT, BIT> {
+    type Error = Infallible;
+}
+
+impl<const PORT: char, const BIT: u8> digital::OutputPin for Gpio<Output, PORT, BIT> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Gpio::set_low(self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Gpio::set_high(self);
+        Ok(())
+    }
+}
+
+impl<const PORT: char, const BIT: u8> digital::StatefulOutputPin for Gpio<Output, PORT, BIT> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Gpio::is_set_high(self))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Gpio::is_set_low(self))
+    }
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        Gpio::toggle(self);
+        Ok(())
+    }
+}
+
+impl<PULL, const PORT: char, const BIT: u8> digital::InputPin for Gpio<Input<PULL>, PORT, BIT> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Gpio::is_high(self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Gpio::is_low(self))
+    }
+}
+
+/// Implements `SetDutyCycle` for each of the given PWM pins through `analog_write`.
+macro_rules! pwm_pins {
+    ($($pin:ident,)*) => {
+        $(
+            impl pwm::ErrorType for gpio::$pin<Output> {
+                type Error = Infallible;
+            }
+
+            impl pwm::SetDutyCycle for gpio::$pin<Output> {
+                fn max_duty_cycle(&self) -> u16 {
+                    u8::MAX as u16
+                }
+
+                fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+                    analog_write(Pin::$pin, duty.min(u8::MAX as u16) as u8);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+pwm_pins!(
+    D3,
+    D5,
+    D6,
+    D9,
+    D10,
+    D11,
+);
+
+/// Blocking delays using cycle counted loops.
+pub struct Delay;
+
+impl delay::DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        delay_cycles(ns as u64 * (CPU_FREQUENCY / 1_000_000) / 1_000);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        delay_micros(us as u64);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        delay_millis(ms as u64);
+    }
+}
+
+/// Various errors that can occur during an I2C transaction.
+#[cfg(any(feature = "twowire", doc))]
+#[doc(cfg(feature = "twowire"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2cError {
+    /// The peripheral did not acknowledge its address.
+    AddressNack,
+    /// The peripheral did not acknowledge a data byte.
+    DataNack,
+    /// The transfer is larger than the TWI buffer.
+    Overrun,
+    /// The bus did not respond in time.
+    Timeout,
+    /// Any other TWI error, such as lost arbitration.
+    Other,
+}
+
+#[cfg(any(feature = "twowire", doc))]
+impl From<WriteError> for I2cError {
+    fn from(value: WriteError) -> I2cError {
+        match value {
+            WriteError::TooLong => I2cError::Overrun,
+            WriteError::SlaNack => I2cError::AddressNack,
+            WriteError::DataNack => I2cError::DataNack,
+            WriteError::Timeout => I2cError::Timeout,
+            WriteError::Other => I2cError::Other,
+        }
+    }
+}
+
+#[cfg(any(feature = "twowire", doc))]
+impl i2c::Error for I2cError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            I2cError::AddressNack => i2c::ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            I2cError::DataNack => i2c::ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            I2cError::Overrun => i2c::ErrorKind::Overrun,
+            I2cError::Timeout | I2cError::Other => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+/// I2C controller using the [`wire`](crate::libraries::wire) library.
+///
+/// `wire::begin()` must be called before any transactions.
+/// Each transaction is limited to the size of the TWI buffer for each run of reads or writes.
+#[cfg(any(feature = "twowire", doc))]
+#[doc(cfg(feature = "twowire"))]
+pub struct I2c;
+
+#[cfg(any(feature = "twowire", doc))]
+impl i2c::ErrorType for I2c {
+    type Error = I2cError;
+}
+
+#[cfg(any(feature = "twowire", doc))]
+impl i2c::I2c for I2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), I2cError> {
+        let mut start = 0;
+
+        while start < operations.len() {
+            // Adjacent operations of the same type are merged into a single transfer
+            let is_write = matches!(operations[start], Operation::Write(_));
+            let mut end = start + 1;
+            while end < operations.len() && matches!(operations[end], Operation::Write(_)) == is_write {
+                end += 1;
+            }
+
+            // Only the last transfer sends a STOP, the others are followed by a repeated START
+            let send_stop = end == operations.len();
+
+            let len: usize = operations[start..end].iter()
+                .map(|op| match op {
+                    Operation::Write(bytes) => bytes.len(),
+                    Operation::Read(buf) => buf.len(),
+                })
+                .sum();
+
+            // Both the tx and rx wire buffers keep one slot empty, so they hold one byte less than their length
+            if len > Buffer::<u8, TWI_BUFFER_LENGTH>::CAPACITY {
+                return Err(I2cError::Overrun);
+            }
+
+            if is_write {
+                wire::begin_transmission(address);
+                for op in &operations[start..end] {
+                    if let Operation::Write(bytes) = op {
+                        for byte in bytes.iter() {
+                            wire::write(*byte).map_err(|_| I2cError::Overrun)?;
+                        }
+                    }
+                }
+                wire::end_transmission(send_stop)?;
+            } else {
+                wire::request_from(address, len as u8, send_stop).map_err(|_| I2cError::Other)?;
+                // The peripheral stopped acknowledging before every byte was read
+                if wire::available() < len {
+                    return Err(I2cError::DataNack);
+                }
+
+                for op in &mut operations[start..end] {
+                    if let Operation::Read(buf) = op {
+                        for byte in buf.iter_mut() {
+                            *byte = wire::read().ok_or(I2cError::Other)?;
+                        }
+                    }
+                }
+            }
+
+            start = end;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the next received byte without blocking.
+#[inline(always)]
+fn try_read() -> Option<u8> {
+    #[cfg(feature = "serial-buffer")]
+    { Serial::read() }
+    #[cfg(not(feature = "serial-buffer"))]
+    { Serial::try_recieve() }
+}
+
+impl embedded_io::Error for serial::Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            serial::Error::Frame | serial::Error::Parity => embedded_io::ErrorKind::InvalidData,
+            serial::Error::Overrun => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io::ErrorType for Serial {
+    type Error = serial::Error;
+}
+
+impl embedded_io::Read for Serial {
+    /// Blocks until at least one byte is received,
+    /// then reads as many bytes as are available without blocking.
+    /// 
+    /// Returns any reception error that occured since the last read.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, serial::Error> {
+        Serial::take_error()?;
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = loop {
+            if let Some(byte) = try_read() {
+                break byte;
+            }
+        };
+
+        let mut len = 1;
+        while len < buf.len() {
+            match try_read() {
+                Some(byte) => {
+                    buf[len] = byte;
+                    len += 1;
+                },
+                None => break,
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+impl embedded_io::Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, serial::Error> {
+        for byte in buf {
+            Serial::transmit(*byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), serial::Error> {
+        // Without the transmit buffer, `transmit` blocks until each byte is handed to the USART
+        #[cfg(feature = "serial-tx-buffer")]
+        Serial::flush();
+        Ok(())
+    }
+}
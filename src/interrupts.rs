@@ -1,12 +1,31 @@
 //! Utilities for controlling global system interrupts
+//!
+//! Data shared with interrupt handlers can be stored in a [`Volatile`] if it is `Copy`,
+//! or in a [`Mutex`], which is borrowed in place inside [`free()`].
+//!
+//! # Example
+//! ```rust,no_run
+//! use core::cell::RefCell;
+//! use atmega::buffer::Buffer;
+//! use atmega::interrupts::{ self, Mutex };
+//!
+//! static SAMPLES: Mutex<RefCell<Buffer<u16, 16>>> = Mutex::new(RefCell::new(Buffer::new()));
+//!
+//! let latest = interrupts::free(|cs| SAMPLES.borrow_ref_mut(cs).read());
+//! ```
 
 use core::arch::asm;
+use core::cell::{ RefCell, Ref, RefMut, UnsafeCell };
+use core::marker::PhantomData;
 use crate::registers::{ SREG, Register };
+use crate::volatile::Volatile;
 
 /// This is a copy of the private `Interrupt` enum in `atmega_macros`
 /// 
-/// This is not used anywhere, but contains all recognized interrupt function names for the `#[interrupt]` attribute
+/// Contains all recognized interrupt function names for the `#[interrupt]` attribute,
+/// and is used as the key for [`set_handler()`].
 #[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Interrupt {
     /// External pin, power-on reset, brown-out reset and watchdog system reset
     RESET        = 0,
@@ -62,6 +81,114 @@ pub enum Interrupt {
     SPM_READY    = 25,
 }
 
+/// Fails at compile time if the given vector is already handled by the crate.
+/// Called by the `#[interrupt]` macro so a conflict names the feature that owns the vector,
+/// instead of causing a duplicate symbol error when linking.
+#[doc(hidden)]
+pub const fn __assert_unowned(vector: u8) {
+    match vector {
+        #[cfg(feature = "external-interrupt")]
+        1 | 2 => panic!("INT0 and INT1 are handled by the `external-interrupt` feature. Use `wiring::attach_interrupt()` instead, or disable the feature."),
+        #[cfg(feature = "pin-change")]
+        3 | 4 | 5 => panic!("PCINT0, PCINT1, and PCINT2 are handled by the `pin-change` feature. Use `pin_change::attach()` instead, or disable the feature."),
+        #[cfg(feature = "watchdog-interrupt")]
+        6 => panic!("WDT is handled by the `watchdog-interrupt` feature. Use `watchdog::attach()` instead, or disable the feature."),
+        #[cfg(feature = "millis")]
+        16 => panic!("TIMER0_OVF is handled by the `millis` feature. Use `timing::on_timer0_overflow()` instead, or disable the feature."),
+        #[cfg(feature = "spi-peripheral")]
+        17 => panic!("SPI_STC is handled by the `spi-peripheral` feature. Use `spi::read()` instead, or disable the feature."),
+        #[cfg(feature = "serial-buffer")]
+        18 => panic!("USART_RX is handled by the `serial-buffer` feature. Use `Serial::read()` instead, or disable the feature."),
+        #[cfg(feature = "serial-tx-buffer")]
+        19 => panic!("USART_UDRE is handled by the `serial-tx-buffer` feature. Disable the feature to handle it yourself."),
+        #[cfg(feature = "twowire")]
+        24 => panic!("TWI is handled by the `twowire` feature. Use `wire::on_receive()` and `wire::on_request()` instead, or disable the feature."),
+        _ => __assert_not_dynamic(vector),
+    }
+}
+
+/// Creates a handler slot and interrupt handler for each vector, enabled by the given feature,
+/// as well as `__assert_not_dynamic()` so the `#[interrupt]` macro can detect conflicts.
+macro_rules! dynamic_handlers {
+    ($($feature:literal => $name:ident[$vector:literal] $slot:ident,)*) => {
+        $(
+            #[cfg(feature = $feature)]
+            static $slot: Volatile<Option<fn()>> = Volatile::new(None);
+
+            #[cfg(feature = $feature)]
+            #[doc(hidden)]
+            #[inline(always)]
+            #[allow(non_snake_case)]
+            #[export_name = $vector]
+            pub unsafe extern "avr-interrupt" fn $name() {
+                if let Some(handler) = $slot.read() {
+                    handler();
+                }
+            }
+        )*
+
+        /// Returns the handler slot of the given interrupt, if its feature is enabled.
+        #[allow(unreachable_patterns)]
+        fn slot(interrupt: Interrupt) -> Option<&'static Volatile<Option<fn()>>> {
+            match interrupt {
+                $(
+                    #[cfg(feature = $feature)]
+                    Interrupt::$name => Some(&$slot),
+                )*
+                _ => None,
+            }
+        }
+
+        #[doc(hidden)]
+        pub const fn __assert_not_dynamic(vector: u8) {
+            $(
+                #[cfg(feature = $feature)]
+                if vector == Interrupt::$name as u8 {
+                    panic!(concat!(stringify!($name), " is handled by the `", $feature, "` feature. Use `interrupts::set_handler()` instead, or disable the feature."));
+                }
+            )*
+            let _ = vector;
+        }
+    };
+}
+
+dynamic_handlers!(
+    "dynamic-timer2-compa" => TIMER2_COMPA["__vector_7"] TIMER2_COMPA_HANDLER,
+    "dynamic-timer2-compb" => TIMER2_COMPB["__vector_8"] TIMER2_COMPB_HANDLER,
+    "dynamic-timer2-ovf" => TIMER2_OVF["__vector_9"] TIMER2_OVF_HANDLER,
+    "dynamic-timer1-capt" => TIMER1_CAPT["__vector_10"] TIMER1_CAPT_HANDLER,
+    "dynamic-timer1-compa" => TIMER1_COMPA["__vector_11"] TIMER1_COMPA_HANDLER,
+    "dynamic-timer1-compb" => TIMER1_COMPB["__vector_12"] TIMER1_COMPB_HANDLER,
+    "dynamic-timer1-ovf" => TIMER1_OVF["__vector_13"] TIMER1_OVF_HANDLER,
+    "dynamic-timer0-compa" => TIMER0_COMPA["__vector_14"] TIMER0_COMPA_HANDLER,
+    "dynamic-timer0-compb" => TIMER0_COMPB["__vector_15"] TIMER0_COMPB_HANDLER,
+    "dynamic-usart-tx" => USART_TX["__vector_20"] USART_TX_HANDLER,
+    "dynamic-adc" => ADC["__vector_21"] ADC_HANDLER,
+    "dynamic-ee-ready" => EE_READY["__vector_22"] EE_READY_HANDLER,
+    "dynamic-analog-comp" => ANALOG_COMP["__vector_23"] ANALOG_COMP_HANDLER,
+    "dynamic-spm-ready" => SPM_READY["__vector_25"] SPM_READY_HANDLER,
+);
+
+/// Sets the function called by the given interrupt, replacing any previous handler.
+///
+/// Only interrupts with their `dynamic-*` feature enabled can be set, such as `dynamic-adc` for `Interrupt::ADC`.
+/// Returns `Err(())` for any other interrupt.
+/// The interrupt itself still has to be enabled in its peripheral's registers.
+pub fn set_handler(interrupt: Interrupt, handler: fn()) -> Result<(), ()> {
+    let slot = slot(interrupt).ok_or(())?;
+    slot.write(Some(handler));
+    Ok(())
+}
+
+/// Removes the function called by the given interrupt, so the interrupt does nothing.
+///
+/// Returns `Err(())` if the interrupt's `dynamic-*` feature is not enabled.
+pub fn clear_handler(interrupt: Interrupt) -> Result<(), ()> {
+    let slot = slot(interrupt).ok_or(())?;
+    slot.write(None);
+    Ok(())
+}
+
 /// Enables global interrupts
 #[inline(always)]
 pub fn enable() {
@@ -129,3 +256,106 @@ pub fn enabled() -> bool {
     // Reads the Global Interrupt Enable bit in the AVR Status Register
     unsafe { SREG::I.read_bit() }
 }
+
+/// A token proving that interrupts are disabled, only available inside [`free()`].
+///
+/// The lifetime keeps anything borrowed with it from escaping the critical section.
+#[derive(Clone, Copy)]
+pub struct CriticalSection<'cs> {
+    _marker: PhantomData<&'cs ()>,
+}
+
+impl<'cs> CriticalSection<'cs> {
+    /// Creates a critical section token.
+    ///
+    /// # Safety
+    /// Interrupts must stay disabled for the lifetime `'cs`.
+    #[inline(always)]
+    pub unsafe fn new() -> Self {
+        CriticalSection { _marker: PhantomData }
+    }
+}
+
+/// Runs a function with interrupts disabled, passing it a [`CriticalSection`] token.
+/// Interrupts are restored to their previous state after.
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce(CriticalSection) -> R,
+{
+    without(State::Restore, || f(unsafe { CriticalSection::new() }))
+}
+
+/// A mutual exclusion primitive for sharing data with interrupts,
+/// which can only be borrowed with a [`CriticalSection`] token.
+///
+/// Unlike [`Volatile`], the data doesn't have to be `Copy`, and is borrowed in place instead of copied.
+/// Wrap the data in a `RefCell` or `Cell` for mutable access.
+pub struct Mutex<T>(UnsafeCell<T>);
+
+impl<T> Mutex<T> {
+    /// Creates a new `Mutex` containing the given data.
+    #[inline(always)]
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex(UnsafeCell::new(value))
+    }
+
+    /// Borrows the data for as long as the critical section lasts.
+    #[inline(always)]
+    pub fn borrow<'cs>(&'cs self, _cs: CriticalSection<'cs>) -> &'cs T {
+        unsafe { &*self.0.get() }
+    }
+
+    /// Returns a mutable reference to the data.
+    /// No critical section is needed, since the `&mut self` guarantees no one else has access.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+
+    /// Consumes the `Mutex` and returns the data contained
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl<T> Mutex<RefCell<T>> {
+    /// Immutably borrows the data inside the `RefCell`.
+    ///
+    /// # Panics
+    /// Panics if the data is currently mutably borrowed.
+    #[inline(always)]
+    pub fn borrow_ref<'cs>(&'cs self, cs: CriticalSection<'cs>) -> Ref<'cs, T> {
+        self.borrow(cs).borrow()
+    }
+
+    /// Mutably borrows the data inside the `RefCell`.
+    ///
+    /// # Panics
+    /// Panics if the data is currently borrowed.
+    #[inline(always)]
+    pub fn borrow_ref_mut<'cs>(&'cs self, cs: CriticalSection<'cs>) -> RefMut<'cs, T> {
+        self.borrow(cs).borrow_mut()
+    }
+}
+
+// SAFETY: the ATmega328p is single-threaded, and the data can only be accessed while interrupts are disabled.
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+/// Implements the `critical-section` crate by disabling interrupts,
+/// so crates built on it can share data with interrupts.
+#[cfg(feature = "critical-section")]
+mod critical_section_impl {
+    struct SingleCore;
+    critical_section::set_impl!(SingleCore);
+
+    unsafe impl critical_section::Impl for SingleCore {
+        unsafe fn acquire() -> u8 {
+            super::disable().0
+        }
+
+        unsafe fn release(sreg: u8) {
+            super::restore(super::Status(sreg));
+        }
+    }
+}
@@ -1,29 +1,74 @@
 //! A fast, easy, recognizable interface for the ATmega328p
 
-#![no_std]
-#![feature(lang_items, asm_experimental_arch, abi_avr_interrupt, error_in_core, doc_cfg, exclusive_range_pattern, maybe_uninit_uninit_array, const_maybe_uninit_uninit_array)]
+#![cfg_attr(not(test), no_std)]
+#![feature(lang_items, asm_experimental_arch, abi_avr_interrupt, error_in_core, doc_cfg, exclusive_range_pattern, maybe_uninit_uninit_array, const_maybe_uninit_uninit_array, naked_functions)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 #![warn(missing_docs)]
 
+// Host tests (`cargo test --lib`) only build the modules that don't use AVR assembly or interrupts
+
+#[cfg(any(feature = "allocator", doc))]
+#[doc(cfg(feature = "allocator"))]
+#[cfg(not(test))]
 pub mod allocator;
 pub mod bits;
 pub mod buffer;
+pub mod collections;
 pub mod constants;
+#[cfg(not(test))]
 pub mod drivers;
+#[cfg(not(test))]
+pub mod eeprom;
+#[cfg(not(test))]
+pub mod gpio;
+#[cfg(any(feature = "embedded-hal", doc))]
+#[doc(cfg(feature = "embedded-hal"))]
+#[cfg(not(test))]
+pub mod hal;
+#[cfg(not(test))]
 pub mod interrupts;
+#[cfg(not(test))]
 pub mod libraries;
+#[cfg(not(test))]
+pub mod memory;
+#[cfg(not(test))]
+pub mod panic;
+#[cfg(any(feature = "pin-change", doc))]
+#[doc(cfg(feature = "pin-change"))]
+#[cfg(not(test))]
+pub mod pin_change;
+#[cfg(not(test))]
+pub mod power;
+#[cfg(not(test))]
 pub mod prelude;
+#[cfg(not(test))]
 pub mod progmem;
 pub mod registers;
+#[cfg(not(test))]
+pub mod reset;
+#[cfg(not(test))]
 pub mod serial;
+#[cfg(any(feature = "millis", doc))]
+#[doc(cfg(feature = "millis"))]
+#[cfg(not(test))]
+pub mod stream;
 pub mod timing;
+#[cfg(not(test))]
 pub mod volatile;
+#[cfg(not(test))]
+pub mod watchdog;
+#[cfg(not(test))]
 pub mod wiring;
 
 #[cfg(any(feature = "interrupt-macro", doc))]
 #[doc(cfg(feature = "interrupt-macro"))]
 pub use atmega_macros::interrupt;
 
+#[cfg(any(feature = "entry-macro", doc))]
+#[doc(cfg(feature = "entry-macro"))]
+pub use atmega_macros::{ entry, loop_fn };
+
+#[cfg(not(test))]
 #[doc(hidden)]
 pub fn _init() {
     wiring::_init();
@@ -55,12 +100,8 @@ macro_rules! run {
     };
 }
 
-/// Panic handler.
-#[panic_handler]
-pub fn panic(_info: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
-
 /// Defines the exception handling personality.
+/// Host tests link `std`, which already defines it.
+#[cfg(not(test))]
 #[lang = "eh_personality"]
 pub extern "C" fn eh_personality() {}
@@ -1,5 +1,9 @@
-//! Libraries to match the official Arduino language such as Wire and TimeLib
+//! Libraries to match the official Arduino language such as Wire, SPI, and TimeLib
 
+#[cfg(any(feature = "software-serial", doc))]
+#[doc(cfg(feature = "software-serial"))]
+pub mod software_serial;
+pub mod spi;
 pub mod time;
 
 #[cfg(any(feature = "twowire", doc))]
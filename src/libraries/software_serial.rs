@@ -0,0 +1,369 @@
+//! Serial communication on any pair of pins via the Arduino [SoftwareSerial](https://github.com/arduino/ArduinoCore-avr/tree/master/libraries/SoftwareSerial) library
+//!
+//! Bits are sent and received with cycle counted delays, so interrupts are disabled while a byte is being transmitted.
+//! Bytes are received from the pin change interrupt of the RX pin, so only one `SoftwareSerial` can listen at a time.
+//! Baud rates from 300 to 57600 are supported at 16 MHz, and from 300 to 28800 at 8 MHz.
+//! Faster rates leave too little of each bit to get from the start of the interrupt to the first sample, so `begin()` rejects them.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::libraries::software_serial::SoftwareSerial;
+//!
+//! let mut gps = SoftwareSerial::new(Pin::D2, Pin::D3);
+//! gps.begin(9600).unwrap();
+//!
+//! loop {
+//!     if let Some(byte) = gps.read() {
+//!         Serial::transmit(byte);
+//!     }
+//! }
+//! ```
+
+use core::fmt::Write;
+use core::ptr::{ read_volatile, write_volatile };
+use crate::buffer::Buffer;
+use crate::constants::CPU_FREQUENCY;
+use crate::interrupts::{ self, State };
+use crate::pin_change::{ self, Edge };
+use crate::registers::{ Register, PINB, PINC, PIND, PORTB, PORTC, PORTD };
+use crate::timing::_delay_loop;
+use crate::volatile::Volatile;
+use crate::wiring::{ Pin, PinMode, Registers, pin_mode, digital_write };
+
+#[cfg(any(feature = "millis", doc))]
+use crate::stream::Stream;
+
+/// Bytes received by whichever instance is listening.
+static RX_BUFFER: Volatile<Buffer<u8, 64>> = Volatile::new(Buffer::new());
+/// Set when a byte is received while the buffer is full.
+static OVERFLOW: Volatile<bool> = Volatile::new(false);
+/// The RX pin and timing of the instance that is listening.
+static LISTENER: Volatile<Option<Listener>> = Volatile::new(None);
+
+/// Delays, in iterations of `_delay_loop()` (4 cycles each).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Delays {
+    /// From the start bit interrupt to the middle of the first data bit
+    centering: u16,
+    /// Between each received data bit
+    intrabit: u16,
+    /// From the last data bit to the end of the stop bit
+    stopbit: u16,
+    /// Between each transmitted bit
+    tx: u16,
+}
+
+/// Cycles spent getting from the pin change to `on_change()` on top of the official library's interrupt overhead:
+/// the `pin_change` dispatch loop, the `Volatile` reads of the handler and `LISTENER`, and the indirect call.
+/// Estimated from the instructions on that path for a pin on bit 7, which is the slowest to reach.
+const DISPATCH_CYCLES: u16 = 100;
+/// Cycles from the start bit's edge to the centering delay, taken from the official library plus `DISPATCH_CYCLES`.
+const CENTERING_CYCLES: u16 = 4 + 4 + 75 + 17 - 23 + DISPATCH_CYCLES;
+/// The shortest bit, in loops, that can still be received.
+/// Once half a bit is shorter than the centering overhead the first sample lands late,
+/// so this keeps it within the first three quarters of the bit, leaving the rest for the error added up over 8 bits.
+const MIN_BIT_DELAY: u64 = CENTERING_CYCLES as u64 / 3;
+
+impl Delays {
+    /// Calculates the delays for the given baud rate.
+    /// Returns `None` if the baud rate is zero, too fast to receive at `CPU_FREQUENCY`, or too slow for a 16 bit delay.
+    const fn new(baud: u32) -> Option<Delays> {
+        if baud == 0 {
+            return None;
+        }
+
+        let bit_delay = CPU_FREQUENCY / baud as u64 / 4;
+        if bit_delay < MIN_BIT_DELAY || bit_delay > u16::MAX as u64 {
+            return None;
+        }
+
+        Some(Self::from_bit_delay(bit_delay as u16))
+    }
+
+    const fn from_bit_delay(bit_delay: u16) -> Delays {
+        // Apart from the centering, the cycle counts subtracted from each delay are taken from the official library.
+        // The inter-bit timings are the most critical, since any error is added up 8 times.
+        Delays {
+            centering: subtract_cap(bit_delay / 2, CENTERING_CYCLES / 4),
+            intrabit: subtract_cap(bit_delay, 23 / 4),
+            stopbit: subtract_cap((bit_delay as u32 * 3 / 4) as u16, (37 + 11) / 4),
+            tx: subtract_cap(bit_delay, 15 / 4),
+        }
+    }
+}
+
+/// Subtracts the overhead from a delay, keeping at least one loop since `_delay_loop(0)` waits for 65536.
+const fn subtract_cap(num: u16, sub: u16) -> u16 {
+    if num > sub {
+        num - sub
+    } else {
+        1
+    }
+}
+
+/// Everything the pin change interrupt needs to receive a byte.
+/// The input register is stored as an address, since raw pointers can't be shared with interrupts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Listener {
+    pin: Pin,
+    reg: usize,
+    mask: u8,
+    inverse: bool,
+    delays: Delays,
+}
+
+/// Returns the input or output register and the bit mask of the given pin.
+fn pin_register(pin: Pin, output: bool) -> (*mut u8, u8) {
+    match Registers::from(pin) {
+        Registers::B(bit) => (if output { PORTB::ADDR } else { PINB::ADDR }, 1 << bit),
+        Registers::C(bit) => (if output { PORTC::ADDR } else { PINC::ADDR }, 1 << bit),
+        Registers::D(bit) => (if output { PORTD::ADDR } else { PIND::ADDR }, 1 << bit),
+    }
+}
+
+/// Reads a byte from the listening pin, called when it changes.
+fn on_change(edge: Edge) {
+    let listener = match LISTENER.read() {
+        Some(listener) => listener,
+        None => return,
+    };
+
+    // The start bit is LOW, or HIGH with inverse logic
+    let start = if listener.inverse { Edge::Rising } else { Edge::Falling };
+    if edge != start {
+        return;
+    }
+
+    // Wait until the middle of the first data bit
+    _delay_loop(listener.delays.centering);
+
+    let mut data: u8 = 0;
+    for _ in 0..8 {
+        _delay_loop(listener.delays.intrabit);
+        data >>= 1;
+        if unsafe { read_volatile(listener.reg as *const u8) } & listener.mask != 0 {
+            data |= 0x80;
+        }
+    }
+
+    if listener.inverse {
+        data = !data;
+    }
+
+    RX_BUFFER.as_mut(|buf| {
+        if buf.available() == 0 {
+            OVERFLOW.write(true);
+        } else {
+            buf.write(data);
+        }
+    });
+
+    // Skip the stop bit, so its rising edge isn't mistaken for a start bit
+    _delay_loop(listener.delays.stopbit);
+}
+
+/// A bit-banged serial port on any two pins.
+pub struct SoftwareSerial {
+    rx: Pin,
+    tx: Pin,
+    inverse: bool,
+    delays: Delays,
+    #[cfg(any(feature = "millis", doc))]
+    timeout: u64,
+}
+
+impl SoftwareSerial {
+    /// Creates a serial port that receives on `rx` and transmits on `tx`.
+    /// Nothing is configured until `begin()` is called.
+    pub const fn new(rx: Pin, tx: Pin) -> SoftwareSerial {
+        Self::with_logic(rx, tx, false)
+    }
+
+    /// Creates a serial port with inverted logic, where a HIGH bit is sent as LOW and the line idles LOW.
+    pub const fn inverted(rx: Pin, tx: Pin) -> SoftwareSerial {
+        Self::with_logic(rx, tx, true)
+    }
+
+    const fn with_logic(rx: Pin, tx: Pin, inverse: bool) -> SoftwareSerial {
+        SoftwareSerial {
+            rx,
+            tx,
+            inverse,
+            // Replaced by `begin()`, this only keeps `write()` from misbehaving before then
+            delays: Delays::from_bit_delay((CPU_FREQUENCY / 9600 / 4) as u16),
+            #[cfg(any(feature = "millis", doc))]
+            timeout: 1000,
+        }
+    }
+
+    /// Configures the pins for the given baud rate and starts listening.
+    ///
+    /// Returns `Err(())` without changing anything if the baud rate is zero or can't be received at `CPU_FREQUENCY`,
+    /// see the [module documentation](self) for the supported range.
+    pub fn begin(&mut self, baud: u32) -> Result<(), ()> {
+        let delays = Delays::new(baud).ok_or(())?;
+
+        // The line idles HIGH, or LOW with inverse logic
+        digital_write(self.tx, !self.inverse);
+        pin_mode(self.tx, PinMode::OUTPUT);
+        pin_mode(self.rx, if self.inverse { PinMode::INPUT } else { PinMode::INPUT_PULLUP });
+
+        self.delays = delays;
+        self.listen();
+        Ok(())
+    }
+
+    /// Stops listening.
+    pub fn end(&mut self) {
+        self.stop_listening();
+    }
+
+    /// Starts receiving on this port, stopping whichever port was listening before.
+    /// Any bytes left in the receive buffer are discarded.
+    ///
+    /// Returns `true` if another port was replaced.
+    pub fn listen(&mut self) -> bool {
+        let (reg, mask) = pin_register(self.rx, false);
+        let listener = Listener {
+            pin: self.rx,
+            reg: reg as usize,
+            mask,
+            inverse: self.inverse,
+            delays: self.delays,
+        };
+
+        let previous = LISTENER.read();
+        if previous == Some(listener) {
+            return false;
+        }
+
+        if let Some(previous) = previous {
+            pin_change::detach(previous.pin);
+        }
+
+        interrupts::without(State::Restore, || {
+            RX_BUFFER.as_mut(|buf| buf.clear());
+            OVERFLOW.write(false);
+            LISTENER.write(Some(listener));
+        });
+        pin_change::attach(self.rx, on_change);
+
+        previous.is_some()
+    }
+
+    /// Returns `true` if this port is the one receiving.
+    pub fn is_listening(&self) -> bool {
+        match LISTENER.read() {
+            Some(listener) => listener.pin == self.rx,
+            None => false,
+        }
+    }
+
+    /// Stops receiving on this port.
+    /// Returns `true` if it was listening.
+    pub fn stop_listening(&mut self) -> bool {
+        if !self.is_listening() {
+            return false;
+        }
+
+        pin_change::detach(self.rx);
+        LISTENER.write(None);
+        true
+    }
+
+    /// Returns `true` if a byte was dropped because the receive buffer was full, clearing the flag.
+    pub fn overflow(&mut self) -> bool {
+        let overflow = OVERFLOW.read();
+        OVERFLOW.write(false);
+        overflow
+    }
+
+    /// Transmits a byte.
+    /// Blocking, and interrupts are disabled during the transmission.
+    pub fn write(&mut self, byte: u8) {
+        let (reg, mask) = pin_register(self.tx, true);
+        let delay = self.delays.tx;
+        let inverse = self.inverse;
+
+        interrupts::without(State::Restore, || unsafe {
+            // Precalculate both values of the port so each bit is a single write
+            let port = read_volatile(reg);
+            let (high, low) = if inverse {
+                (port & !mask, port | mask)
+            } else {
+                (port | mask, port & !mask)
+            };
+
+            // Start bit
+            write_volatile(reg, low);
+            _delay_loop(delay);
+
+            let mut data = byte;
+            for _ in 0..8 {
+                write_volatile(reg, if data & 1 != 0 { high } else { low });
+                _delay_loop(delay);
+                data >>= 1;
+            }
+
+            // Stop bit
+            write_volatile(reg, high);
+            _delay_loop(delay);
+        });
+    }
+
+    /// The number of received bytes waiting to be read.
+    pub fn available(&self) -> usize {
+        if !self.is_listening() {
+            return 0;
+        }
+        RX_BUFFER.as_deref(|buf| buf.len())
+    }
+
+    /// Reads the next received byte, if there is one.
+    pub fn read(&mut self) -> Option<u8> {
+        if !self.is_listening() {
+            return None;
+        }
+        RX_BUFFER.as_mut(|buf| buf.read())
+    }
+
+    /// Returns the next received byte without removing it, if there is one.
+    pub fn peek(&self) -> Option<u8> {
+        if !self.is_listening() {
+            return None;
+        }
+        RX_BUFFER.as_deref(|buf| if buf.is_empty() { None } else { Some(buf[0]) })
+    }
+}
+
+impl Write for SoftwareSerial {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            SoftwareSerial::write(self, byte);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Stream for SoftwareSerial {
+    fn available(&mut self) -> usize {
+        SoftwareSerial::available(self)
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        SoftwareSerial::read(self)
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        SoftwareSerial::peek(self)
+    }
+
+    fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+}
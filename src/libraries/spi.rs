@@ -0,0 +1,295 @@
+//! Implementation of the SPI protocol via the Arduino [SPI](https://github.com/arduino/ArduinoCore-avr/tree/master/libraries/SPI) library
+//!
+//! The hardware SPI uses `D10` as SS, `D11` as MOSI, `D12` as MISO and `D13` as SCK.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::libraries::spi::{ self, SpiSettings, BitOrder, Mode };
+//!
+//! spi::begin();
+//! spi::begin_transaction(SpiSettings::new(1_000_000, BitOrder::MsbFirst, Mode::Mode0));
+//! let reply = spi::transfer(0x42);
+//! spi::end_transaction();
+//! ```
+
+use core::arch::asm;
+use crate::constants::CPU_FREQUENCY;
+use crate::registers::{ Register, SPCR, SPSR, SPDR };
+use crate::wiring::{ Pin, PinMode, pin_mode, digital_write, HIGH, LOW };
+
+#[cfg(any(feature = "spi-peripheral", doc))]
+use crate::buffer::Buffer;
+#[cfg(any(feature = "spi-peripheral", doc))]
+use crate::volatile::Volatile;
+
+/// Slave select pin
+pub const SS: Pin = Pin::D10;
+/// Controller out, peripheral in
+pub const MOSI: Pin = Pin::D11;
+/// Controller in, peripheral out
+pub const MISO: Pin = Pin::D12;
+/// Serial clock
+pub const SCK: Pin = Pin::D13;
+
+#[cfg(feature = "spi-peripheral")]
+static SPI_BUFFER: Volatile<Buffer<u8, 32>> = Volatile::new(Buffer::new());
+#[cfg(feature = "spi-peripheral")]
+static SPI_REPLY: Volatile<u8> = Volatile::new(0);
+
+/// The order bits are shifted out onto the bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOrder {
+    /// Most significant bit first
+    MsbFirst,
+    /// Least significant bit first
+    LsbFirst,
+}
+
+/// The clock polarity and phase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Clock idles low, data is sampled on the rising edge
+    Mode0,
+    /// Clock idles low, data is sampled on the falling edge
+    Mode1,
+    /// Clock idles high, data is sampled on the falling edge
+    Mode2,
+    /// Clock idles high, data is sampled on the rising edge
+    Mode3,
+}
+
+/// The division applied to the CPU clock to get the SPI clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockDivider {
+    #[allow(missing_docs)]
+    Div2,
+    #[allow(missing_docs)]
+    Div4,
+    #[allow(missing_docs)]
+    Div8,
+    #[allow(missing_docs)]
+    Div16,
+    #[allow(missing_docs)]
+    Div32,
+    #[allow(missing_docs)]
+    Div64,
+    #[allow(missing_docs)]
+    Div128,
+}
+
+impl ClockDivider {
+    /// Picks the smallest divider that results in a clock at or below the given frequency.
+    pub fn from_frequency(clock: u64) -> ClockDivider {
+        use ClockDivider::*;
+        match clock {
+            _ if clock >= CPU_FREQUENCY / 2  => Div2,
+            _ if clock >= CPU_FREQUENCY / 4  => Div4,
+            _ if clock >= CPU_FREQUENCY / 8  => Div8,
+            _ if clock >= CPU_FREQUENCY / 16 => Div16,
+            _ if clock >= CPU_FREQUENCY / 32 => Div32,
+            _ if clock >= CPU_FREQUENCY / 64 => Div64,
+            _ => Div128,
+        }
+    }
+
+    /// Returns the `SPR1`, `SPR0`, and `SPI2X` bits for this divider.
+    fn bits(&self) -> (bool, bool, bool) {
+        use ClockDivider::*;
+        match self {
+            Div2   => (false, false, true),
+            Div4   => (false, false, false),
+            Div8   => (false, true,  true),
+            Div16  => (false, true,  false),
+            Div32  => (true,  false, true),
+            Div64  => (true,  false, false),
+            Div128 => (true,  true,  false),
+        }
+    }
+}
+
+/// The clock, bit order, and mode used during a transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpiSettings {
+    /// The clock divider
+    pub clock: ClockDivider,
+    /// The order bits are transferred
+    pub bit_order: BitOrder,
+    /// The clock polarity and phase
+    pub mode: Mode,
+}
+
+impl SpiSettings {
+    /// Creates new settings with a clock as close to the given frequency as possible without exceeding it.
+    pub fn new(clock: u64, bit_order: BitOrder, mode: Mode) -> SpiSettings {
+        SpiSettings { clock: ClockDivider::from_frequency(clock), bit_order, mode }
+    }
+
+    /// Creates new settings with the given clock divider.
+    pub const fn with_divider(clock: ClockDivider, bit_order: BitOrder, mode: Mode) -> SpiSettings {
+        SpiSettings { clock, bit_order, mode }
+    }
+
+    /// Writes the settings into `SPCR` and `SPSR`, keeping the enable, interrupt, and controller bits.
+    fn apply(&self) {
+        use SPCR::*;
+
+        let (spr1, spr0, spi2x) = self.clock.bits();
+        let (cpol, cpha) = match self.mode {
+            Mode::Mode0 => (false, false),
+            Mode::Mode1 => (false, true),
+            Mode::Mode2 => (true,  false),
+            Mode::Mode3 => (true,  true),
+        };
+
+        unsafe {
+            let mut spcr = SPCR::read() & (SPE.bv() | SPIE.bv() | MSTR.bv());
+            if spr0 { spcr |= SPR0.bv() }
+            if spr1 { spcr |= SPR1.bv() }
+            if cpha { spcr |= CPHA.bv() }
+            if cpol { spcr |= CPOL.bv() }
+            if self.bit_order == BitOrder::LsbFirst { spcr |= DORD.bv() }
+
+            SPCR::write(spcr);
+            SPSR::SPI2X.set_value(spi2x);
+        }
+    }
+}
+
+impl Default for SpiSettings {
+    /// 4MHz, most significant bit first, mode 0
+    fn default() -> SpiSettings {
+        SpiSettings::new(4_000_000, BitOrder::MsbFirst, Mode::Mode0)
+    }
+}
+
+/// Initializes the SPI bus as a controller.
+///
+/// Sets SCK and MOSI to outputs, and drives SS high so that a connected peripheral is deselected.
+pub fn begin() {
+    // Set SS high before making it an output so a connected chip isn't briefly selected
+    digital_write(SS, HIGH);
+    pin_mode(SS, PinMode::OUTPUT);
+
+    unsafe {
+        // Warning: if the SS pin ever becomes a LOW INPUT then SPI
+        // automatically switches to peripheral mode, so the data direction
+        // of the SS pin MUST be kept as OUTPUT.
+        SPCR::MSTR.set();
+        SPCR::SPE.set();
+    }
+
+    pin_mode(SCK, PinMode::OUTPUT);
+    pin_mode(MOSI, PinMode::OUTPUT);
+}
+
+/// Disables the SPI bus, leaving the pin modes unchanged.
+pub fn end() {
+    unsafe {
+        SPCR::SPE.clear();
+        SPCR::SPIE.clear();
+    }
+}
+
+/// Applies the given settings and selects the peripheral by driving SS low.
+pub fn begin_transaction(settings: SpiSettings) {
+    settings.apply();
+    digital_write(SS, LOW);
+}
+
+/// Deselects the peripheral by driving SS high.
+pub fn end_transaction() {
+    digital_write(SS, HIGH);
+}
+
+/// Transmits a byte while receiving one from the peripheral.
+/// Blocking
+pub fn transfer(data: u8) -> u8 {
+    unsafe {
+        SPDR::write(data);
+        // The following NOP introduces a small delay that can prevent the wait
+        // loop from iterating when running at the maximum speed. This gives
+        // about 10% more speed, even if it seems counter-intuitive. At lower
+        // speeds it is unnoticed.
+        asm!("nop");
+        while !SPSR::SPIF.read_bit() {}
+        SPDR::read()
+    }
+}
+
+/// Transmits two bytes while receiving two from the peripheral.
+/// The byte order follows the current bit order.
+pub fn transfer16(data: u16) -> u16 {
+    let [msb, lsb] = data.to_be_bytes();
+
+    if unsafe { SPCR::DORD.read_bit() } {
+        let lsb = transfer(lsb);
+        let msb = transfer(msb);
+        u16::from_be_bytes([msb, lsb])
+    } else {
+        let msb = transfer(msb);
+        let lsb = transfer(lsb);
+        u16::from_be_bytes([msb, lsb])
+    }
+}
+
+/// Transmits every byte in the buffer, replacing each with the byte received in its place.
+pub fn transfer_in_place(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        *byte = transfer(*byte);
+    }
+}
+
+/// Initializes the SPI bus as a peripheral, storing received bytes in a buffer.
+///
+/// Only the bit order and mode of the settings are used, since the controller drives the clock.
+#[cfg(any(feature = "spi-peripheral", doc))]
+#[doc(cfg(feature = "spi-peripheral"))]
+pub fn begin_peripheral(settings: SpiSettings) {
+    pin_mode(SS, PinMode::INPUT);
+    pin_mode(SCK, PinMode::INPUT);
+    pin_mode(MOSI, PinMode::INPUT);
+    pin_mode(MISO, PinMode::OUTPUT);
+
+    SPI_BUFFER.as_mut(|buf| buf.clear());
+
+    unsafe {
+        SPCR::MSTR.clear();
+        settings.apply();
+        SPDR::write(SPI_REPLY.read());
+
+        SPCR::SPIE.set();
+        SPCR::SPE.set();
+    }
+}
+
+/// Sets the byte that will be shifted out during the next transfer from the controller.
+#[cfg(any(feature = "spi-peripheral", doc))]
+#[doc(cfg(feature = "spi-peripheral"))]
+pub fn set_reply(byte: u8) {
+    SPI_REPLY.write(byte);
+}
+
+/// The total bytes received as a peripheral that have not been read yet.
+#[cfg(any(feature = "spi-peripheral", doc))]
+#[doc(cfg(feature = "spi-peripheral"))]
+pub fn available() -> usize {
+    SPI_BUFFER.as_deref(|buf| buf.len())
+}
+
+/// Read the byte at the front of the peripheral receive buffer.
+#[cfg(any(feature = "spi-peripheral", doc))]
+#[doc(cfg(feature = "spi-peripheral"))]
+pub fn read() -> Option<u8> {
+    SPI_BUFFER.as_mut(|buf| buf.read())
+}
+
+#[cfg(feature = "spi-peripheral")]
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_17"]
+pub unsafe extern "avr-interrupt" fn SPI_STC() {
+    SPI_BUFFER.as_mut(|buf| buf.write(SPDR::read()));
+    // Load the reply for the next transfer
+    SPDR::write(SPI_REPLY.read());
+}
@@ -1,24 +1,34 @@
 //! Implementation of the I2C protocol via the Arduino [Wire](https://github.com/arduino/ArduinoCore-avr/tree/master/libraries/Wire) library
 #![allow(non_upper_case_globals, unused_must_use)]
 
+use core::cell::RefCell;
+use crate::interrupts::{ self, Mutex };
 use crate::volatile::Volatile;
 use crate::buffer::Buffer;
 
+#[cfg(any(feature = "millis", doc))]
+use crate::stream::Stream;
+
 mod util;
-use util::TWI_BUFFER_LENGTH;
+pub use util::{ TWI_BUFFER_LENGTH, WriteError };
 
-static rx_buffer: Volatile<Buffer<TWI_BUFFER_LENGTH>> = Volatile::new(Buffer::new());
-static tx_buffer: Volatile<Buffer<TWI_BUFFER_LENGTH>> = Volatile::new(Buffer::new());
+static rx_buffer: Mutex<RefCell<Buffer<u8, TWI_BUFFER_LENGTH>>> = Mutex::new(RefCell::new(Buffer::new()));
+static tx_buffer: Mutex<RefCell<Buffer<u8, TWI_BUFFER_LENGTH>>> = Mutex::new(RefCell::new(Buffer::new()));
 static tx_address: Volatile<u8> = Volatile::new(0);
 static transmitting: Volatile<bool> = Volatile::new(false);
 
 static user_on_receive: Volatile<Option<fn(usize)>> = Volatile::new(None);
 static user_on_request: Volatile<Option<fn()>> = Volatile::new(None);
 
+#[cfg(any(feature = "millis", doc))]
+static stream_timeout: Volatile<u64> = Volatile::new(1000);
+
 /// Initialize TWI interface 
 pub fn begin() {
-    rx_buffer.as_mut(|buf| buf.clear());
-    tx_buffer.as_mut(|buf| buf.clear());
+    interrupts::free(|cs| {
+        rx_buffer.borrow_ref_mut(cs).clear();
+        tx_buffer.borrow_ref_mut(cs).clear();
+    });
 
     util::twi_init();
 
@@ -100,7 +110,8 @@ pub fn request_from(address: u8, quantity: u8, send_stop: bool) -> Result<(), ()
     let clamped = quantity.min(TWI_BUFFER_LENGTH as u8);
 
     let read = util::read_from(address, clamped, send_stop)?;
-    rx_buffer.as_mut(|buf| {
+    interrupts::free(|cs| {
+        let mut buf = rx_buffer.borrow_ref_mut(cs);
         buf.clear();
         for byte in read {
             buf.write(byte);
@@ -117,7 +128,7 @@ pub fn begin_transmission(address: u8) {
     // Set address of targeted peripheral
     tx_address.write(address);
     // Reset tx_buffer
-    tx_buffer.as_mut(|buf| buf.clear());
+    interrupts::free(|cs| tx_buffer.borrow_ref_mut(cs).clear());
 }
 
 /// Originally, `end transmission` was an `fn()` function.
@@ -133,9 +144,14 @@ pub fn begin_transmission(address: u8) {
 /// devices will behave oddly if they do not see a STOP.
 pub fn end_transmission(send_stop: bool) -> Result<(), util::WriteError> {
     // Transmit buffer (blocking)
-    let ret = util::write_to(tx_address.read(), tx_buffer.read(), true, send_stop);
-    // Reset tx buffer
-    tx_buffer.as_mut(|buf| buf.clear());
+    let data = interrupts::free(|cs| {
+        let mut buf = tx_buffer.borrow_ref_mut(cs);
+        let data = *buf;
+        // Reset tx buffer
+        buf.clear();
+        data
+    });
+    let ret = util::write_to(tx_address.read(), data, true, send_stop);
     // Indicate that we are done transmitting
     transmitting.write(false);
 
@@ -146,27 +162,31 @@ pub fn end_transmission(send_stop: bool) -> Result<(), util::WriteError> {
 pub fn write(data: u8) -> Result<(), ()> {
     if transmitting.read() {
     // In controller transmitter mode
-        // Don't bother if buffer is full
-        if tx_buffer.read().is_full() {
-            return Err(());
-        }
-        // put byte in tx buffer
-        tx_buffer.as_mut(|buf| buf.write(data));
+        interrupts::free(|cs| {
+            let mut buf = tx_buffer.borrow_ref_mut(cs);
+            // Don't bother if buffer is full
+            if buf.is_full() {
+                return Err(());
+            }
+            // put byte in tx buffer
+            buf.write(data);
+            Ok(())
+        })?;
     } else {
     // In peripheral send mode
         // Reply to controller
-        util::twi_transmit(Buffer::<1>::from_slice(&[data]));
+        util::twi_transmit(&[data]);
     }
 
     Ok(())
 }
 
 /// Must be called in `peripheral tx event callback` or after `begin_transmission(address)`
-pub fn write_all<const SIZE: usize>(data: Buffer<SIZE>) {
+pub fn write_all(data: &[u8]) {
     if transmitting.read() {
     // In controller transmitter mode
         for byte in data {
-            write(byte);
+            write(*byte);
         }
     } else {
     // In peripheral send mode
@@ -179,22 +199,21 @@ pub fn write_all<const SIZE: usize>(data: Buffer<SIZE>) {
 /// 
 /// Must be called in `peripheral rx event callback` or after `request_from(address, num_bytes)`
 pub fn available() -> usize {
-    rx_buffer.read().len()
+    interrupts::free(|cs| rx_buffer.borrow_ref(cs).len())
 }
 
 /// Reads the byte at the front of the rx buffer if there is any data available;.
 pub fn read() -> Option<u8> {
-    rx_buffer.read().read()
+    interrupts::free(|cs| rx_buffer.borrow_ref_mut(cs).read())
 }
 
 /// Must be called in `peripheral_rx_event_callback()`
 /// or after `request_from(address, num_bytes)`
 pub fn peek() -> Option<u8> {
-    if rx_buffer.read().is_empty() {
-        return None;
-    }
-
-    Some(rx_buffer.read()[0])
+    interrupts::free(|cs| {
+        let buf = rx_buffer.borrow_ref(cs);
+        if buf.is_empty() { None } else { Some(buf[0]) }
+    })
 }
 
 /// `flush()` is unimplemented in the official library, 
@@ -204,21 +223,53 @@ pub fn flush() {
      // XXX: unimplemented
 }
 
-fn on_receive_service(bytes_in: Buffer<TWI_BUFFER_LENGTH>) {
+/// The rx side of the TWI interface, allowing received data to be parsed as a [`Stream`].
+#[cfg(any(feature = "millis", doc))]
+#[doc(cfg(feature = "millis"))]
+pub struct Wire;
+
+#[cfg(any(feature = "millis", doc))]
+impl Stream for Wire {
+    fn available(&mut self) -> usize {
+        available()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        read()
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        peek()
+    }
+
+    fn timeout(&self) -> u64 {
+        stream_timeout.read()
+    }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        stream_timeout.write(timeout);
+    }
+}
+
+fn on_receive_service(bytes_in: Buffer<u8, TWI_BUFFER_LENGTH>) {
     // don't bother if rx buffer is in use by a controller request_from() op
     // I know this drops data, but it allows for slight supidity
     // meaning, they may not have read all the controller request_from() data yet
-    if !rx_buffer.read().is_empty() {
+    if !interrupts::free(|cs| rx_buffer.borrow_ref(cs).is_empty()) {
         return;
     }
 
     if let Some(callback) = user_on_receive.read() {
         // Copy twi rx buffer into local read buffewr
         // This enables new reads to happen in parallel
-        for byte in bytes_in {
-            rx_buffer.as_mut(|buf| buf.write(byte));
-        }
-        callback(bytes_in.len());
+        let len = bytes_in.len();
+        interrupts::free(|cs| {
+            let mut buf = rx_buffer.borrow_ref_mut(cs);
+            for byte in bytes_in {
+                buf.write(byte);
+            }
+        });
+        callback(len);
     }
 }
 
@@ -227,7 +278,7 @@ fn on_request_service() {
     if let Some(callback) = user_on_request.read() {
         // Reset tx buffer
         // !!! This will kill any pending pre-controller send_to() activity
-        tx_buffer.as_mut(|buf| buf.clear());
+        interrupts::free(|cs| tx_buffer.borrow_ref_mut(cs).clear());
 
         callback();
     }
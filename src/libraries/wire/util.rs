@@ -8,8 +8,8 @@ use crate::wiring::{ digital_write, Pin };
 use crate::constants::CPU_FREQUENCY;
 use crate::prelude::delay_micros;
 use crate::volatile::Volatile;
-use crate::buffer::Buffer;
-use crate::timing::micros;
+use crate::buffer::{ Buffer, Queue };
+use crate::timing::{ Deadline, Duration };
 
 #[derive(Clone, Copy, PartialEq)]
 enum State {
@@ -130,6 +130,7 @@ impl Flags {
     }
 }
 
+/// The size of the TWI buffers in bytes
 pub const TWI_BUFFER_LENGTH: usize = 32;
 
 static twi_state: Volatile<State> = Volatile::new(State::READY);
@@ -147,15 +148,25 @@ static twi_timeout_us: Volatile<u32> = Volatile::new(0);
 static twi_timed_out_flag: Volatile<bool> = Volatile::new(false);       // a timeout has been seen
 static twi_do_reset_on_timeout: Volatile<bool> = Volatile::new(false); // reset the TWI registers on timeout
 
+/// Starts timing a wait on the TWI hardware, which never expires if the timeout is disabled.
+fn timeout_deadline() -> Deadline {
+    Deadline::after_or_never(Duration::from_micros(twi_timeout_us.read()))
+}
+
 fn blank_transmit() {}
 static twi_on_peripheral_transmit: Volatile<fn()> = Volatile::new(blank_transmit);
 
-fn blank_receive(_buf: Buffer<TWI_BUFFER_LENGTH>) {}
-static twi_on_peripheral_receive: Volatile<fn(Buffer<TWI_BUFFER_LENGTH>)> = Volatile::new(blank_receive);
+fn blank_receive(_buf: Buffer<u8, TWI_BUFFER_LENGTH>) {}
+static twi_on_peripheral_receive: Volatile<fn(Buffer<u8, TWI_BUFFER_LENGTH>)> = Volatile::new(blank_receive);
 
-static twi_master_buffer: Volatile<Buffer<TWI_BUFFER_LENGTH>> = Volatile::new(Buffer::new());
-static twi_tx_buffer: Volatile<Buffer<TWI_BUFFER_LENGTH>> = Volatile::new(Buffer::new());
-static twi_rx_buffer: Volatile<Buffer<TWI_BUFFER_LENGTH>> = Volatile::new(Buffer::new());
+// Each queue only has one producer and one consumer at a time:
+// the master buffer is produced by the caller and consumed by the ISR while transmitting, and the other way around while receiving,
+// the tx buffer is produced by `twi_transmit()` and consumed by the ISR,
+// and the rx buffer is produced and consumed by the ISR.
+// The caller only clears the master buffer while the ISR is idle.
+static twi_master_buffer: Queue<u8, TWI_BUFFER_LENGTH> = Queue::new();
+static twi_tx_buffer: Queue<u8, TWI_BUFFER_LENGTH> = Queue::new();
+static twi_rx_buffer: Queue<u8, TWI_BUFFER_LENGTH> = Queue::new();
 
 static twi_error: Volatile<u8> = Volatile::new(0xFF);
 
@@ -199,17 +210,17 @@ pub fn set_frequency(frequency: u64) {
     unsafe { TWBR::write((((CPU_FREQUENCY / frequency) - 16)/2) as u8); }
 }
 
-pub fn read_from(address: u8, length: u8, send_stop: bool) -> Result<Buffer<TWI_BUFFER_LENGTH>, ()> {
+pub fn read_from(address: u8, length: u8, send_stop: bool) -> Result<Buffer<u8, TWI_BUFFER_LENGTH>, ()> {
     // Ensure data will fit into buffer
     if TWI_BUFFER_LENGTH < length as usize {
         return Err(());
     }
 
-    let start_micros = micros();
+    let deadline = timeout_deadline();
 
     unsafe {
         while twi_state.read() != State::READY  {
-            if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+            if deadline.is_expired() {
                 twi_handle_timeout(twi_do_reset_on_timeout.read());
                 return Err(());
             }
@@ -220,17 +231,17 @@ pub fn read_from(address: u8, length: u8, send_stop: bool) -> Result<Buffer<TWI_
 
         twi_error.write(0xFF);
 
-        twi_master_buffer.as_mut(|buf| buf.clear());
+        twi_master_buffer.consumer().clear();
 
         twi_slarw.write(TW_READ | (address << 1));
 
         if twi_in_rep_start.read() {
             twi_in_rep_start.write(false);
-            let start_micros = micros();
+            let deadline = timeout_deadline();
 
             while TWCR::TWWC.read_bit() {
                 TWDR::write(twi_slarw.read());
-                if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+                if deadline.is_expired() {
                     twi_handle_timeout(twi_do_reset_on_timeout.read());
                     return Err(());
                 }
@@ -249,28 +260,29 @@ pub fn read_from(address: u8, length: u8, send_stop: bool) -> Result<Buffer<TWI_
             TWCR::TWSTA.set();
         }
         
-        let start_micros = micros();
+        let deadline = timeout_deadline();
         while twi_state.read() == State::MRX {
-            if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+            if deadline.is_expired() {
                 twi_handle_timeout(twi_do_reset_on_timeout.read());
                 return Err(());
             }
         }
         
-        let len = twi_master_buffer.read().len().min(length as usize);
-
-        let mut ret: Buffer<TWI_BUFFER_LENGTH> = Buffer::new();
-        for i in 0..len {
-            ret.write(twi_master_buffer.read()[i]);
+        let mut ret: Buffer<u8, TWI_BUFFER_LENGTH> = Buffer::new();
+        for byte in twi_master_buffer.consumer().take(length as usize) {
+            ret.write(byte);
         }
 
         Ok(ret)
     }
 }
 
+/// Errors that can occur while transmitting to a peripheral
 pub enum WriteError {
+    /// Data too long to fit in the transmit buffer
+    TooLong = 1,
     /// Address send, NACK received
-    SlaNack = 2,
+    SlaNack,
     /// Data send, NACK received
     DataNack,
     /// Other TWI error
@@ -279,26 +291,29 @@ pub enum WriteError {
     Timeout,
 }
 
-pub fn write_to(address: u8, data: Buffer<TWI_BUFFER_LENGTH>, wait: bool, send_stop: bool) -> Result<(), WriteError> {
-    let start_micros = micros();
+pub fn write_to(address: u8, data: Buffer<u8, TWI_BUFFER_LENGTH>, wait: bool, send_stop: bool) -> Result<(), WriteError> {
+    let deadline = timeout_deadline();
     while twi_state.read() != State::READY {
-        if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+        if deadline.is_expired() {
             twi_handle_timeout(twi_do_reset_on_timeout.read());
             return Err(WriteError::Timeout);
         }
     }
 
+    // The ISR doesn't touch the master buffer until the state changes
+    unsafe {
+        twi_master_buffer.consumer().clear();
+        let mut producer = twi_master_buffer.producer();
+        for byte in data {
+            producer.enqueue(byte).map_err(|_| WriteError::TooLong)?;
+        }
+    }
+
     twi_state.write(State::MTX);
     twi_send_stop.write(send_stop);
     // Reset error state 0xFF.. no error occured)
     twi_error.write(0xFF);
 
-    twi_master_buffer.as_mut(|buf| buf.clear());
-
-    for byte in data {
-        twi_master_buffer.as_mut(|buf| buf.write(byte));
-    }
-
     // Build sla+w, peripheral device address + w bit
     twi_slarw.write(TW_WRITE | (address << 1));
 
@@ -308,11 +323,11 @@ pub fn write_to(address: u8, data: Buffer<TWI_BUFFER_LENGTH>, wait: bool, send_s
     if twi_in_rep_start.read() {
         twi_in_rep_start.write(false);
         
-        let start_micros = micros();
+        let deadline = timeout_deadline();
         unsafe {
             while TWCR::TWWC.read_bit() {
                 TWDR::write(twi_slarw.read());
-                if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+                if deadline.is_expired() {
                     twi_handle_timeout(twi_do_reset_on_timeout.read());
                     return Err(WriteError::Timeout);
                 }
@@ -326,9 +341,9 @@ pub fn write_to(address: u8, data: Buffer<TWI_BUFFER_LENGTH>, wait: bool, send_s
     }
 
     // Wait for write operation to complete
-    let start_micros = micros();
+    let deadline = timeout_deadline();
     while wait && twi_state.read() == State::MTX {
-        if twi_timeout_us.read() > 0 && (micros() - start_micros) > twi_timeout_us.read() as u64 {
+        if deadline.is_expired() {
             twi_handle_timeout(twi_do_reset_on_timeout.read());
             return Err(WriteError::Timeout);
         }
@@ -349,10 +364,11 @@ pub enum TransmitStatus {
 }
 
 /// Fills peripheral tx buffer with data.
-pub fn twi_transmit<const SIZE: usize>(data: Buffer<SIZE>) -> TransmitStatus {
+pub fn twi_transmit(data: &[u8]) -> TransmitStatus {
+    let mut producer = unsafe { twi_tx_buffer.producer() };
+
     // Ensure data will fit into buffer
-    let tx_len = twi_tx_buffer.read().len();
-    if TWI_BUFFER_LENGTH < (tx_len + data.len()) {
+    if data.len() > producer.available() {
         return TransmitStatus::TooLarge;
     }
 
@@ -363,13 +379,15 @@ pub fn twi_transmit<const SIZE: usize>(data: Buffer<SIZE>) -> TransmitStatus {
 
     // Copy data into tx buffer
     for byte in data {
-        twi_tx_buffer.as_mut(|buf| buf.write(byte));
+        if producer.enqueue(*byte).is_err() {
+            return TransmitStatus::TooLarge;
+        }
     }
 
     TransmitStatus::Ok
 }
 
-pub fn twi_attach_peripheral_rx_event(callback: fn(Buffer<TWI_BUFFER_LENGTH>)) {
+pub fn twi_attach_peripheral_rx_event(callback: fn(Buffer<u8, TWI_BUFFER_LENGTH>)) {
     twi_on_peripheral_receive.write(callback);
 }
 
@@ -393,7 +411,7 @@ pub fn twi_stop() {
 
     // Wait for stop condition to be executed on bus
     // TWINT is not set after a stop condition!
-    // We can't use micros() from an ISR, since micros relies on interrutps, so approximate the timeout with cycle-counted delays
+    // We can't use a Deadline from an ISR, since the time relies on interrupts, so approximate the timeout with cycle-counted delays
     const US_PER_LOOP: u32 = 8;
     let mut counter = (twi_timeout_us.read() + US_PER_LOOP - 1) / US_PER_LOOP; // Round up
     while unsafe { TWCR::TWSTO.read_bit() } {
@@ -460,7 +478,7 @@ pub unsafe extern "avr-interrupt" fn TWI() {
         },
         TW_CT_DATA_ACK => {
             // If there is data to send, send it, otherwise stop
-            if let Some(data) = twi_master_buffer.as_mut(|buf| buf.read()) {
+            if let Some(data) = twi_master_buffer.consumer().dequeue() {
                 TWDR::write(data);
                 twi_reply(true);
             } else {
@@ -493,14 +511,14 @@ pub unsafe extern "avr-interrupt" fn TWI() {
         // Master Receiver
         TW_CR_DATA_ACK => { // Data received, ACK sent
             // Put byte into buffer
-            twi_master_buffer.as_mut(|buf| buf.write(TWDR::read()));
+            let _ = twi_master_buffer.producer().enqueue(TWDR::read());
         },
         TW_CR_SLA_ACK => { // Address sent, ACK reeceived
             // ACK if more bytes are expected, otherwise NACK
-            twi_reply(twi_master_buffer.read().len() > 0)
+            twi_reply(twi_master_buffer.len() > 0)
         },
         TW_CR_DATA_NACK => { // Data received, NACK sent
-            twi_master_buffer.as_mut(|buf| buf.write(TWDR::read()));
+            let _ = twi_master_buffer.producer().enqueue(TWDR::read());
             if twi_send_stop.read() {
                 twi_stop();
             } else {
@@ -523,14 +541,14 @@ pub unsafe extern "avr-interrupt" fn TWI() {
             // Enter peripheral receiver mode
             twi_state.write(State::SRX);
             //Indicate that rx buffer can be overwritten and ACK
-            twi_rx_buffer.as_mut(|buf| buf.clear());
+            twi_rx_buffer.consumer().clear();
             twi_reply(true);
         },
         TW_PR_GCALL_DATA_ACK => { // Data received generallty, returned ACK
             // If there is still room in the rx buffer
-            if !twi_rx_buffer.read().is_full() {
+            if !twi_rx_buffer.is_full() {
                 // Put byte in buffer and ACK
-                twi_rx_buffer.as_mut(|buf| buf.write(TWDR::read()));
+                let _ = twi_rx_buffer.producer().enqueue(TWDR::read());
                 twi_reply(true);
             } else {
                 // otherwise NACK
@@ -541,11 +559,14 @@ pub unsafe extern "avr-interrupt" fn TWI() {
             // ACK future responses and leave peripheral receiver state
             twi_release_bus();
             //Put a null char after data if there's room
-            twi_rx_buffer.as_mut(|buf| buf.write('\0' as u8));
+            let _ = twi_rx_buffer.producer().enqueue('\0' as u8);
             // Callback to the user defined callback
-            twi_on_peripheral_receive.read()(twi_rx_buffer.read());
-            // Since we submit rx buffer to "wire" library, we can reset it
-            twi_rx_buffer.as_mut(|buf| buf.clear());
+            // Draining the rx buffer into the callback also resets it
+            let mut received: Buffer<u8, TWI_BUFFER_LENGTH> = Buffer::new();
+            for byte in twi_rx_buffer.consumer() {
+                received.write(byte);
+            }
+            twi_on_peripheral_receive.read()(received);
         },
         TW_PR_GCALL_DATA_NACK => { // Data received generally, returned NACK
             twi_reply(false);
@@ -556,18 +577,18 @@ pub unsafe extern "avr-interrupt" fn TWI() {
             // Enter peripheral transmitter mode
             twi_state.write(State::STX);
             // Ready the tx buffer for iteration
-            twi_tx_buffer.as_mut(|buf| buf.clear());
+            twi_tx_buffer.consumer().clear();
             // Request for tx buffer to be filled
             // Note: User must call twi_transmit(bytes) to do this
             twi_on_peripheral_transmit.read()();
         },
         TW_PT_DATA_ACK => { // Byte sent, ACK returned
             // Copy data to output register
-            if let Some(byte) = twi_tx_buffer.as_mut(|buf| buf.read()) {
+            if let Some(byte) = twi_tx_buffer.consumer().dequeue() {
                 TWDR::write(byte);
             }
             //If there is more to send, ACK, otherwise NACK
-            twi_reply(!twi_tx_buffer.read().is_empty());
+            twi_reply(!twi_tx_buffer.is_empty());
         },
         TW_PT_LAST_DATA => { // Received ACK, but we are done already!
             // ACK future responses
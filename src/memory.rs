@@ -0,0 +1,196 @@
+//! Diagnostics for the 2 KiB of SRAM shared by static memory, the heap, and the stack.
+//!
+//! Static memory sits at the bottom of SRAM, followed by the heap (with the `allocator` feature), which grows up towards the stack.
+//! The stack starts at [`RAMEND`] and grows down.
+//!
+//! With the `stack-paint` feature, the free space is filled with a known value before `main()` is called,
+//! so the deepest point the stack has reached can be found with [`stack_high_water_mark()`].
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::memory;
+//!
+//! Serial::begin(9600);
+//! println!("{}", memory::usage());
+//! memory::check_collision();
+//! ```
+
+use core::fmt;
+use crate::registers::{ Register, SPL, SPH };
+
+#[cfg(feature = "stack-paint")]
+use core::arch::asm;
+#[cfg(any(feature = "stack-paint", doc))]
+use core::ptr::read_volatile;
+
+/// The first address of SRAM.
+pub const RAMSTART: usize = 0x0100;
+/// The last address of SRAM, where the stack starts.
+pub const RAMEND: usize = 0x08FF;
+
+/// The value free memory is painted with at startup.
+#[cfg(any(feature = "stack-paint", doc))]
+#[doc(cfg(feature = "stack-paint"))]
+pub const CANARY: u8 = 0xC5;
+
+extern "C" {
+    /// The end of static memory, defined by the linker script.
+    static mut __heap_start: u8;
+}
+
+/// Paints everything from the end of static memory to the top of SRAM.
+/// Placed in `.init1` so it runs before the stack is used or static memory is initialized,
+/// and falls through to the rest of the startup code.
+#[cfg(feature = "stack-paint")]
+#[doc(hidden)]
+#[naked]
+#[no_mangle]
+#[link_section = ".init1"]
+pub unsafe extern "C" fn __paint_stack() {
+    asm!(
+        "ldi r30, lo8(__heap_start)",
+        "ldi r31, hi8(__heap_start)",
+        // CANARY
+        "ldi r24, 0xC5",
+        "ldi r25, hi8(__stack)",
+        "1:",
+        "st Z+, r24",
+        "cpi r30, lo8(__stack)",
+        "cpc r31, r25",
+        "brlo 1b",
+        "breq 1b",
+        options(noreturn),
+    );
+}
+
+/// Paints the given range of memory, used when the heap shrinks so it isn't mistaken for used stack.
+#[cfg(feature = "stack-paint")]
+pub(crate) unsafe fn paint(start: usize, end: usize) {
+    for addr in start..end {
+        core::ptr::write_volatile(addr as *mut u8, CANARY);
+    }
+}
+
+/// Reads the current stack pointer.
+/// The stack pointer points to the next free byte, so everything above it is in use.
+#[inline(always)]
+pub fn stack_pointer() -> usize {
+    unsafe { ((SPH::read() as usize) << 8) | SPL::read() as usize }
+}
+
+/// The address of the top of the heap, or the end of static memory if there is no heap.
+fn heap_end() -> usize {
+    #[cfg(feature = "allocator")]
+    { crate::allocator::heap_end() }
+    #[cfg(not(feature = "allocator"))]
+    { unsafe { core::ptr::addr_of_mut!(__heap_start) as usize } }
+}
+
+/// The bytes of static memory used by `.data` and `.bss`.
+pub fn static_memory() -> usize {
+    unsafe { core::ptr::addr_of_mut!(__heap_start) as usize - RAMSTART }
+}
+
+/// The bytes of SRAM that are not in use,
+/// including the space between the heap and the stack and any freed chunks on the heap.
+pub fn free_memory() -> usize {
+    let free = stack_pointer().saturating_sub(heap_end());
+
+    #[cfg(feature = "allocator")]
+    { free + crate::allocator::heap_free() }
+    #[cfg(not(feature = "allocator"))]
+    { free }
+}
+
+/// The bytes currently used by the stack.
+pub fn stack_used() -> usize {
+    RAMEND - stack_pointer()
+}
+
+/// The most bytes the stack has used since startup,
+/// found by searching for the lowest byte that is no longer painted.
+///
+/// A byte on the stack that happens to match [`CANARY`] can cause the result to be a few bytes low.
+#[cfg(any(feature = "stack-paint", doc))]
+#[doc(cfg(feature = "stack-paint"))]
+pub fn stack_high_water_mark() -> usize {
+    let sp = stack_pointer();
+    let mut addr = heap_end();
+
+    while addr <= sp {
+        if unsafe { read_volatile(addr as *const u8) } != CANARY {
+            return RAMEND + 1 - addr;
+        }
+        addr += 1;
+    }
+
+    RAMEND - sp
+}
+
+/// Returns `true` if the stack has grown into the heap or static memory.
+pub fn collided() -> bool {
+    let end = heap_end();
+
+    #[cfg(feature = "stack-paint")]
+    if unsafe { read_volatile(end as *const u8) } != CANARY {
+        return true;
+    }
+
+    stack_pointer() < end
+}
+
+/// Calls the panic handler if the stack has grown into the heap or static memory.
+pub fn check_collision() {
+    if collided() {
+        panic!("stack collided with heap");
+    }
+}
+
+/// A snapshot of SRAM usage, which can be printed with `println!`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    /// The bytes used by `.data` and `.bss`
+    pub static_memory: usize,
+    /// The bytes currently allocated on the heap, or 0 without the `allocator` feature
+    pub heap_used: usize,
+    /// The most bytes allocated on the heap at once, or 0 without the `allocator` feature
+    pub heap_peak: usize,
+    /// The bytes currently used by the stack
+    pub stack_used: usize,
+    /// The most bytes used by the stack, or the current usage without the `stack-paint` feature
+    pub stack_peak: usize,
+    /// The bytes that are not in use
+    pub free: usize,
+}
+
+/// Takes a snapshot of SRAM usage.
+pub fn usage() -> Usage {
+    #[cfg(feature = "allocator")]
+    let (heap_used, heap_peak) = (crate::allocator::heap_used(), crate::allocator::heap_peak());
+    #[cfg(not(feature = "allocator"))]
+    let (heap_used, heap_peak) = (0, 0);
+
+    #[cfg(feature = "stack-paint")]
+    let stack_peak = stack_high_water_mark();
+    #[cfg(not(feature = "stack-paint"))]
+    let stack_peak = stack_used();
+
+    Usage {
+        static_memory: static_memory(),
+        heap_used,
+        heap_peak,
+        stack_used: stack_used(),
+        stack_peak,
+        free: free_memory(),
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "static: {}B, heap: {}B (peak {}B), stack: {}B (peak {}B), free: {}B",
+            self.static_memory, self.heap_used, self.heap_peak, self.stack_used, self.stack_peak, self.free,
+        )
+    }
+}
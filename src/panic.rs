@@ -0,0 +1,92 @@
+//! The panic handler, selected with features.
+//!
+//! - `panic-halt` (default) disables interrupts and loops forever.
+//! - `panic-serial` prints the location and message of the panic with `println!` if `Serial` has been started.
+//! - `panic-blink` blinks a pin (`D13` by default, see [`set_blink_pin()`]) in a repeating pattern of three short flashes.
+//! - `panic-reset` resets the chip through the watchdog.
+//!
+//! `panic-serial` can be combined with any of the others, which run after the message is printed.
+//! If more than one of `panic-halt`, `panic-blink`, and `panic-reset` are enabled, reset takes priority, then blink.
+//!
+//! With none of these features, no panic handler is defined, so the application has to provide its own `#[panic_handler]`.
+//! Since `panic-halt` is a default feature, this requires `default-features = false`.
+
+#[cfg(any(feature = "panic-halt", feature = "panic-serial", feature = "panic-blink", feature = "panic-reset"))]
+use core::panic::PanicInfo;
+#[cfg(any(feature = "panic-halt", feature = "panic-serial", feature = "panic-blink", feature = "panic-reset"))]
+use crate::interrupts;
+
+#[cfg(any(feature = "panic-blink", doc))]
+use crate::volatile::Volatile;
+#[cfg(any(feature = "panic-blink", doc))]
+use crate::wiring::Pin;
+
+#[cfg(any(feature = "panic-blink", doc))]
+static BLINK_PIN: Volatile<Pin> = Volatile::new(Pin::D13);
+
+/// Sets the pin blinked by the panic handler.
+/// Defaults to `D13`, which is the built in LED on most boards.
+#[cfg(any(feature = "panic-blink", doc))]
+#[doc(cfg(feature = "panic-blink"))]
+pub fn set_blink_pin(pin: Pin) {
+    BLINK_PIN.write(pin);
+}
+
+// Host tests use the panic handler from `std`
+#[cfg(all(not(test), any(feature = "panic-halt", feature = "panic-serial", feature = "panic-blink", feature = "panic-reset")))]
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    interrupts::disable();
+
+    #[cfg(feature = "panic-serial")]
+    print_info(_info);
+
+    #[cfg(feature = "panic-reset")]
+    {
+        crate::watchdog::enable(crate::watchdog::Timeout::Ms16);
+        loop {}
+    }
+
+    #[cfg(all(feature = "panic-blink", not(feature = "panic-reset")))]
+    blink();
+
+    #[cfg(not(any(feature = "panic-blink", feature = "panic-reset")))]
+    loop {}
+}
+
+/// Prints the panic over serial, as long as the transmitter is enabled.
+#[cfg(feature = "panic-serial")]
+fn print_info(info: &PanicInfo) {
+    use crate::registers::{ Register, UCSR0B };
+
+    // Transmitting before `Serial::begin()` would wait forever
+    if unsafe { !UCSR0B::TXEN0.read_bit() } {
+        return;
+    }
+
+    crate::println!("{}", info);
+
+    // Make sure the message is out before a reset
+    #[cfg(feature = "serial-tx-buffer")]
+    crate::serial::Serial::flush();
+}
+
+/// Blinks the pin three times quickly, then pauses, forever.
+#[cfg(all(feature = "panic-blink", not(feature = "panic-reset")))]
+fn blink() -> ! {
+    use crate::timing::delay;
+    use crate::wiring::{ PinMode, pin_mode, digital_write, HIGH, LOW };
+
+    let pin = BLINK_PIN.read();
+    pin_mode(pin, PinMode::OUTPUT);
+
+    loop {
+        for _ in 0..3 {
+            digital_write(pin, HIGH);
+            delay(100);
+            digital_write(pin, LOW);
+            delay(100);
+        }
+        delay(800);
+    }
+}
@@ -0,0 +1,138 @@
+//! Pin change interrupts, which allow any pin to trigger a callback when its value changes.
+//!
+//! Each port shares a single interrupt (`PCINT0` for port B, `PCINT1` for port C, and `PCINT2` for port D),
+//! so the previous value of each port is kept to work out which pins changed, and in which direction.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::pin_change::{ self, Edge };
+//!
+//! fn encoder(edge: Edge) {
+//!     if edge == Edge::Rising {
+//!         digital_toggle(Pin::D13);
+//!     }
+//! }
+//!
+//! pin_mode(Pin::A0, PinMode::INPUT_PULLUP);
+//! pin_change::attach(Pin::A0, encoder);
+//! ```
+
+use crate::bits;
+use crate::interrupts::{ self, State };
+use crate::registers::{ Register, PCICR, PCMSK0, PCMSK1, PCMSK2, PINB, PINC, PIND };
+use crate::volatile::Volatile;
+use crate::wiring::{ Pin, Registers };
+
+/// The direction a pin changed in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Edge {
+    /// The pin went from LOW to HIGH
+    Rising,
+    /// The pin went from HIGH to LOW
+    Falling,
+}
+
+const NO_HANDLER: Volatile<Option<fn(Edge)>> = Volatile::new(None);
+const NO_STATE: Volatile<u8> = Volatile::new(0);
+
+/// Callbacks for each pin, indexed by port then bit.
+static HANDLERS: [[Volatile<Option<fn(Edge)>>; 8]; 3] = [[NO_HANDLER; 8]; 3];
+/// The value of each port the last time it was checked.
+static PREVIOUS: [Volatile<u8>; 3] = [NO_STATE; 3];
+
+/// Returns the port index (B = 0, C = 1, D = 2) and bit of the given pin.
+/// The port index matches the pin change interrupt number.
+fn port_bit(pin: Pin) -> (usize, u8) {
+    match Registers::from(pin) {
+        Registers::B(bit) => (0, bit),
+        Registers::C(bit) => (1, bit),
+        Registers::D(bit) => (2, bit),
+    }
+}
+
+/// Reads the input register of the given port.
+fn read_port(port: usize) -> u8 {
+    unsafe {
+        match port {
+            0 => PINB::read(),
+            1 => PINC::read(),
+            _ => PIND::read(),
+        }
+    }
+}
+
+/// Sets or clears the given bit in the mask register of the port,
+/// and enables or disables the port interrupt depending on whether any pins are left.
+unsafe fn set_mask(port: usize, bit: u8, value: bool) {
+    let mask = match port {
+        0 => { PCMSK0::operate(|val| bits::set_value(val, bit, value)); PCMSK0::read() },
+        1 => { PCMSK1::operate(|val| bits::set_value(val, bit, value)); PCMSK1::read() },
+        _ => { PCMSK2::operate(|val| bits::set_value(val, bit, value)); PCMSK2::read() },
+    };
+
+    PCICR::operate(|val| bits::set_value(val, port as u8, mask != 0));
+}
+
+/// Calls `handler` from an interrupt whenever the value of the given pin changes.
+/// Replaces any handler previously attached to the pin.
+pub fn attach(pin: Pin, handler: fn(Edge)) {
+    let (port, bit) = port_bit(pin);
+
+    HANDLERS[port][bit as usize].write(Some(handler));
+
+    interrupts::without(State::Restore, || unsafe {
+        // Record the current value so the first change is reported in the right direction
+        let current = bits::read(read_port(port), bit);
+        PREVIOUS[port].operate(|val| bits::set_value(val, bit, current));
+
+        set_mask(port, bit, true);
+    });
+}
+
+/// Stops reporting changes on the given pin and removes its handler.
+pub fn detach(pin: Pin) {
+    let (port, bit) = port_bit(pin);
+
+    interrupts::without(State::Restore, || unsafe { set_mask(port, bit, false) });
+    HANDLERS[port][bit as usize].write(None);
+}
+
+/// Compares the port against its previous value, and calls the handler of every enabled pin that changed.
+#[inline(always)]
+fn dispatch(port: usize, mask: u8) {
+    let current = read_port(port);
+    let changed = (PREVIOUS[port].read() ^ current) & mask;
+    PREVIOUS[port].write(current);
+
+    for bit in 0..8 {
+        if bits::read(changed, bit) {
+            if let Some(handler) = HANDLERS[port][bit as usize].read() {
+                handler(if bits::read(current, bit) { Edge::Rising } else { Edge::Falling });
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_3"]
+pub unsafe extern "avr-interrupt" fn PCINT0() {
+    dispatch(0, PCMSK0::read());
+}
+
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_4"]
+pub unsafe extern "avr-interrupt" fn PCINT1() {
+    dispatch(1, PCMSK1::read());
+}
+
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_5"]
+pub unsafe extern "avr-interrupt" fn PCINT2() {
+    dispatch(2, PCMSK2::read());
+}
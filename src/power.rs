@@ -0,0 +1,178 @@
+//! Sleep modes and power reduction, for keeping current draw down when there's nothing to do.
+//!
+//! | Mode                | Wake sources                                                          | `millis()` |
+//! |---------------------|-----------------------------------------------------------------------|------------|
+//! | `Idle`              | Any interrupt                                                         | Keeps time |
+//! | `AdcNoiseReduction` | ADC, watchdog, INT0/INT1, pin change, Timer 2 async, TWI address match | Stops      |
+//! | `PowerDown`         | Watchdog, INT0/INT1 (level only), pin change, TWI address match       | Stops      |
+//! | `PowerSave`         | Same as `PowerDown`, plus Timer 2 async                               | Stops      |
+//! | `Standby`           | Same as `PowerDown`                                                   | Stops      |
+//! | `ExtendedStandby`   | Same as `PowerSave`                                                   | Stops      |
+//!
+//! `Standby` and `ExtendedStandby` keep the main oscillator running, so they wake up in 6 cycles instead of around 16k.
+//!
+//! Wake sources are set up with [`watchdog::enable_with()`](crate::watchdog::enable_with),
+//! [`attach_interrupt()`](crate::wiring::attach_interrupt), and [`pin_change::attach()`](crate::pin_change::attach).
+//! Timer 0 keeps running in `Idle`, so `millis()` stays correct, but it stops in every other mode.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::power::{ self, Mode, Peripheral };
+//! use atmega::watchdog::{ self, Timeout };
+//!
+//! power::disable_peripheral(Peripheral::Adc);
+//! power::disable_peripheral(Peripheral::Spi);
+//!
+//! loop {
+//!     // Sleep for about 8 seconds, woken up by the watchdog interrupt
+//!     watchdog::enable_with(Timeout::S8, watchdog::Mode::Interrupt);
+//!     power::sleep_bod_disabled(Mode::PowerDown);
+//!     watchdog::disable();
+//! }
+//! ```
+
+use core::arch::asm;
+use crate::interrupts::{ self, State };
+use crate::registers::{ Register, ADCSRA, ASSR, PRR, SMCR };
+
+/// The sleep mode, from lightest to deepest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The CPU stops, but all peripherals keep running.
+    Idle = 0b000,
+    /// Only the ADC, external interrupts, TWI, Timer 2, and watchdog keep running,
+    /// which reduces noise during ADC conversions.
+    AdcNoiseReduction = 0b001,
+    /// Every clock stops, leaving only the watchdog and asynchronous interrupts.
+    PowerDown = 0b010,
+    /// Like `PowerDown`, but Timer 2 keeps running if it is clocked asynchronously.
+    PowerSave = 0b011,
+    /// Like `PowerDown`, but the main oscillator keeps running.
+    Standby = 0b110,
+    /// Like `PowerSave`, but the main oscillator keeps running.
+    ExtendedStandby = 0b111,
+}
+
+impl Mode {
+    /// Returns `true` if Timer 2 can keep running asynchronously in this mode.
+    fn timer2_async(self) -> bool {
+        matches!(self, Mode::AdcNoiseReduction | Mode::PowerSave | Mode::ExtendedStandby)
+    }
+}
+
+/// Peripherals that can be shut down through the Power Reduction Register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Peripheral {
+    /// The analog to digital converter, used by `analog_read()`
+    Adc,
+    /// The USART, used by `Serial`
+    Usart0,
+    /// The SPI interface
+    Spi,
+    /// Timer 1, used for PWM on `D9` and `D10`
+    Timer1,
+    /// Timer 0, used by `millis()` and PWM on `D5` and `D6`
+    Timer0,
+    /// Timer 2, used for PWM on `D3` and `D11`
+    Timer2,
+    /// The two-wire interface, used by `wire`
+    Twi,
+}
+
+impl Peripheral {
+    fn bit(self) -> PRR {
+        match self {
+            Peripheral::Adc => PRR::PRADC,
+            Peripheral::Usart0 => PRR::PRUSART0,
+            Peripheral::Spi => PRR::PRSPI0,
+            Peripheral::Timer1 => PRR::PRTIM1,
+            Peripheral::Timer0 => PRR::PRTIM0,
+            Peripheral::Timer2 => PRR::PRTIM2,
+            Peripheral::Twi => PRR::PRTWI0,
+        }
+    }
+}
+
+/// Sleeps in the given mode until an interrupt wakes the CPU.
+/// Global interrupts are enabled, since nothing could wake the CPU otherwise.
+pub fn sleep(mode: Mode) {
+    prepare(mode);
+
+    unsafe {
+        asm!(
+            // The instruction after sei is always executed before any interrupt,
+            // so an interrupt can't sneak in between and leave us sleeping
+            "sei",
+            "sleep",
+            options(nomem, nostack),
+        );
+        SMCR::SE.clear();
+    }
+}
+
+/// Sleeps in the given mode with the brown-out detector disabled, saving around 20µA.
+/// Only has an effect in `PowerDown`, `PowerSave`, `Standby`, and `ExtendedStandby`.
+/// Global interrupts are enabled, since nothing could wake the CPU otherwise.
+pub fn sleep_bod_disabled(mode: Mode) {
+    prepare(mode);
+
+    unsafe {
+        asm!(
+            // BODS has to be written within 4 cycles of setting BODSE,
+            // and only stays set for 3 cycles
+            "in {mcucr}, 0x35",
+            "ori {mcucr}, 0x60",
+            "out 0x35, {mcucr}",
+            "andi {mcucr}, 0xDF",
+            "out 0x35, {mcucr}",
+            "sei",
+            "sleep",
+            mcucr = out(reg_upper) _,
+            options(nomem, nostack),
+        );
+        SMCR::SE.clear();
+    }
+}
+
+/// Disables interrupts and selects the sleep mode, leaving sleep enabled.
+fn prepare(mode: Mode) {
+    // Timer 2 registers written in asynchronous mode have to finish updating,
+    // otherwise waking up from the timer can fail
+    if mode.timer2_async() && unsafe { ASSR::AS2.read_bit() } {
+        use ASSR::*;
+        let busy = TCN2UB.bv() | OCR2AUB.bv() | OCR2BUB.bv() | TCR2AUB.bv() | TCR2BUB.bv();
+        while unsafe { ASSR::read() } & busy != 0 {}
+    }
+
+    interrupts::disable();
+    unsafe { SMCR::write(((mode as u8) << 1) | SMCR::SE.bv()) };
+}
+
+/// Shuts down the clock to the given peripheral to save power.
+/// The peripheral can't be used until it is enabled again.
+///
+/// Disabling `Timer0` stops `millis()`.
+pub fn disable_peripheral(peripheral: Peripheral) {
+    interrupts::without(State::Restore, || unsafe {
+        if peripheral == Peripheral::Adc {
+            // The ADC has to be turned off before it's shut down, or it will stay on
+            ADCSRA::ADEN.clear();
+        }
+        peripheral.bit().set();
+    });
+}
+
+/// Restarts the clock to the given peripheral.
+pub fn enable_peripheral(peripheral: Peripheral) {
+    interrupts::without(State::Restore, || unsafe {
+        peripheral.bit().clear();
+        if peripheral == Peripheral::Adc {
+            ADCSRA::ADEN.set();
+        }
+    });
+}
+
+/// Returns `true` if the given peripheral has not been shut down.
+pub fn is_enabled(peripheral: Peripheral) -> bool {
+    unsafe { !peripheral.bit().read_bit() }
+}
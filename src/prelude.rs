@@ -1,6 +1,7 @@
 //! Re-exports of important traits, types, macros, and functions used with atmega. Meant to be glob imported.
 
 pub use crate::serial::Serial;
+pub use crate::gpio::Pins;
 pub use crate::registers::Register;
 pub use crate::{ run, print, println };
 pub use crate::timing::{ delay, delay_micros };
@@ -9,3 +10,10 @@ pub use crate::wiring::{ Pin, PinMode, HIGH, LOW, pin_mode, digital_read, digita
 #[cfg(any(feature = "millis", doc))]
 #[doc(cfg(feature = "millis"))]
 pub use crate::timing::millis;
+#[cfg(any(feature = "millis", doc))]
+#[doc(cfg(feature = "millis"))]
+pub use crate::stream::{ Stream, Lookahead };
+
+#[cfg(any(feature = "external-interrupt", doc))]
+#[doc(cfg(feature = "external-interrupt"))]
+pub use crate::wiring::{ Trigger, attach_interrupt, detach_interrupt };
@@ -329,6 +329,83 @@ pub enum MCUCR {
     BODS  = 6,
 }
 
+/// External Interrupt Control Register A
+#[derive(Clone, Copy)]
+pub enum EICRA {
+    ISC00 = 0,
+    ISC01 = 1,
+    ISC10 = 2,
+    ISC11 = 3,
+}
+
+/// External Interrupt Mask Register
+#[derive(Clone, Copy)]
+pub enum EIMSK {
+    INT0 = 0,
+    INT1 = 1,
+}
+
+/// External Interrupt Flag Register
+#[derive(Clone, Copy)]
+pub enum EIFR {
+    INTF0 = 0,
+    INTF1 = 1,
+}
+
+/// Pin Change Interrupt Control Register
+#[derive(Clone, Copy)]
+pub enum PCICR {
+    PCIE0 = 0,
+    PCIE1 = 1,
+    PCIE2 = 2,
+}
+
+/// Pin Change Interrupt Flag Register
+#[derive(Clone, Copy)]
+pub enum PCIFR {
+    PCIF0 = 0,
+    PCIF1 = 1,
+    PCIF2 = 2,
+}
+
+/// Pin Change Mask Register 0
+#[derive(Clone, Copy)]
+pub enum PCMSK0 {
+    PCINT0 = 0,
+    PCINT1 = 1,
+    PCINT2 = 2,
+    PCINT3 = 3,
+    PCINT4 = 4,
+    PCINT5 = 5,
+    PCINT6 = 6,
+    PCINT7 = 7,
+}
+
+/// Pin Change Mask Register 1
+#[derive(Clone, Copy)]
+pub enum PCMSK1 {
+    PCINT8  = 0,
+    PCINT9  = 1,
+    PCINT10 = 2,
+    PCINT11 = 3,
+    PCINT12 = 4,
+    PCINT13 = 5,
+    PCINT14 = 6,
+}
+
+/// Pin Change Mask Register 2
+#[derive(Clone, Copy)]
+pub enum PCMSK2 {
+    PCINT16 = 0,
+    PCINT17 = 1,
+    PCINT18 = 2,
+    PCINT19 = 3,
+    PCINT20 = 4,
+    PCINT21 = 5,
+    PCINT22 = 6,
+    PCINT23 = 7,
+}
+
 /// Power Reduction Register
 #[derive(Clone, Copy)]
 pub enum PRR {
@@ -742,8 +819,150 @@ pub enum TWAR {
     TWA6  = 7,
 }
 
+/// SPI Control Register
+#[derive(Clone, Copy)]
+pub enum SPCR {
+    SPR0 = 0,
+    SPR1 = 1,
+    CPHA = 2,
+    CPOL = 3,
+    MSTR = 4,
+    DORD = 5,
+    SPE  = 6,
+    SPIE = 7,
+}
+
+/// SPI Status Register
+#[derive(Clone, Copy)]
+pub enum SPSR {
+    SPI2X = 0,
+    WCOL  = 6,
+    SPIF  = 7,
+}
+
+/// SPI Data Register
+#[derive(Clone, Copy)]
+pub enum SPDR {
+    SPD0 = 0,
+    SPD1 = 1,
+    SPD2 = 2,
+    SPD3 = 3,
+    SPD4 = 4,
+    SPD5 = 5,
+    SPD6 = 6,
+    SPD7 = 7,
+}
+
+/// EEPROM Control Register
+#[derive(Clone, Copy)]
+pub enum EECR {
+    EERE  = 0,
+    EEPE  = 1,
+    EEMPE = 2,
+    EERIE = 3,
+    EEPM0 = 4,
+    EEPM1 = 5,
+}
+
+/// EEPROM Data Register
+#[derive(Clone, Copy)]
+pub enum EEDR {
+    EEDR0 = 0,
+    EEDR1 = 1,
+    EEDR2 = 2,
+    EEDR3 = 3,
+    EEDR4 = 4,
+    EEDR5 = 5,
+    EEDR6 = 6,
+    EEDR7 = 7,
+}
+
+/// EEPROM Address Register Low
+#[derive(Clone, Copy)]
+pub enum EEARL {
+    EEAR0 = 0,
+    EEAR1 = 1,
+    EEAR2 = 2,
+    EEAR3 = 3,
+    EEAR4 = 4,
+    EEAR5 = 5,
+    EEAR6 = 6,
+    EEAR7 = 7,
+}
+
+/// EEPROM Address Register High
+#[derive(Clone, Copy)]
+pub enum EEARH {
+    EEAR8 = 0,
+    EEAR9 = 1,
+}
+
+/// Watchdog Timer Control Register
+#[derive(Clone, Copy)]
+pub enum WDTCSR {
+    WDP0 = 0,
+    WDP1 = 1,
+    WDP2 = 2,
+    WDE  = 3,
+    WDCE = 4,
+    WDP3 = 5,
+    WDIE = 6,
+    WDIF = 7,
+}
+
+/// MCU Status Register
+#[derive(Clone, Copy)]
+pub enum MCUSR {
+    PORF  = 0,
+    EXTRF = 1,
+    BORF  = 2,
+    WDRF  = 3,
+}
+
+/// Sleep Mode Control Register
+#[derive(Clone, Copy)]
+pub enum SMCR {
+    SE  = 0,
+    SM0 = 1,
+    SM1 = 2,
+    SM2 = 3,
+}
+
+/// Asynchronous Status Register
+#[derive(Clone, Copy)]
+pub enum ASSR {
+    TCR2BUB = 0,
+    TCR2AUB = 1,
+    OCR2BUB = 2,
+    OCR2AUB = 3,
+    TCN2UB  = 4,
+    AS2     = 5,
+    EXCLK   = 6,
+}
+
+/// Stack Pointer Low
+#[derive(Clone, Copy)]
+pub enum SPL {
+    SP0 = 0,
+    SP1 = 1,
+    SP2 = 2,
+    SP3 = 3,
+    SP4 = 4,
+    SP5 = 5,
+    SP6 = 6,
+    SP7 = 7,
+}
+
+/// Stack Pointer High
+#[derive(Clone, Copy)]
+pub enum SPH {
+    SP8  = 0,
+    SP9  = 1,
+    SP10 = 2,
+}
+
 register!(
-    SREG[0x3F],
+    SREG[0x5F],
     ADCSRA[0x7A], 
     ADCSRB[0x7B], 
     ADMUX[0x7C], 
@@ -757,6 +976,14 @@ register!(
     DDRD[0x2A],
     PORTD[0x2B],
     MCUCR[0x55],
+    EICRA[0x69],
+    EIMSK[0x3D],
+    EIFR[0x3C],
+    PCICR[0x68],
+    PCIFR[0x3B],
+    PCMSK0[0x6B],
+    PCMSK1[0x6C],
+    PCMSK2[0x6D],
     PRR[0x64],
     GRCCR[0x43],
     TIFR0[0x35],
@@ -793,6 +1020,19 @@ register!(
     TWCR[0xBC],
     TWBR[0xB8],
     TWAR[0xBA],
+    EECR[0x3F],
+    EEDR[0x40],
+    EEARL[0x41],
+    EEARH[0x42],
+    SPCR[0x4C],
+    SPSR[0x4D],
+    SPDR[0x4E],
+    SPL[0x5D],
+    SPH[0x5E],
+    WDTCSR[0x60],
+    MCUSR[0x54],
+    SMCR[0x53],
+    ASSR[0xB6],
 );
 
 /// Port B maps to pins `D13`-`D8`,
@@ -0,0 +1,96 @@
+//! Finding out what caused the last reset.
+//!
+//! The reset flags in `MCUSR` are captured at the very start of startup, before static memory is initialized or `wiring::_init()` is called.
+//! `MCUSR` is then cleared so the flags are accurate on the next boot,
+//! and the watchdog is disabled if it caused the reset so a short timeout can't cause a reset loop.
+//!
+//! Optiboot clears `MCUSR` before starting the program, but passes the original value in `r2`,
+//! which is used when `MCUSR` is empty.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::reset::{ self, ResetCause };
+//!
+//! Serial::begin(9600);
+//! if reset::reset_cause() == ResetCause::Watchdog {
+//!     println!("recovered from a hang");
+//! }
+//! ```
+
+use core::arch::asm;
+use core::mem::MaybeUninit;
+use crate::registers::{ Register, MCUSR };
+
+/// The value of `MCUSR` at reset.
+/// Kept in `.noinit` since `.bss` is cleared after it is written.
+#[link_section = ".noinit"]
+static mut RESET_FLAGS: MaybeUninit<u8> = MaybeUninit::uninit();
+
+/// The cause of the last reset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetCause {
+    /// The power supply was connected
+    PowerOn,
+    /// The `RESET` pin was pulled low, such as by the reset button or the USB serial adapter
+    External,
+    /// The supply voltage dropped below the brown-out detector level
+    BrownOut,
+    /// The watchdog timed out
+    Watchdog,
+    /// No reset flags were set, such as after jumping to the reset vector in software
+    Unknown,
+}
+
+/// The raw reset flags from `MCUSR`, which can have more than one flag set.
+pub fn reset_flags() -> u8 {
+    unsafe { RESET_FLAGS.assume_init() }
+}
+
+/// Returns the cause of the last reset.
+/// If more than one flag is set, a power on takes priority, followed by the watchdog, a brown-out, and then the reset pin.
+pub fn reset_cause() -> ResetCause {
+    use MCUSR::*;
+    let flags = reset_flags();
+
+    if flags & PORF.bv() != 0 {
+        ResetCause::PowerOn
+    } else if flags & WDRF.bv() != 0 {
+        ResetCause::Watchdog
+    } else if flags & BORF.bv() != 0 {
+        ResetCause::BrownOut
+    } else if flags & EXTRF.bv() != 0 {
+        ResetCause::External
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Captures and clears `MCUSR`, then disables the watchdog if it caused the reset.
+/// Placed in `.init3` so it runs right after the stack is set up, and falls through to the rest of the startup code.
+#[doc(hidden)]
+#[naked]
+#[no_mangle]
+#[link_section = ".init3"]
+pub unsafe extern "C" fn __capture_reset() {
+    asm!(
+        // Use the value saved by the bootloader if it already cleared MCUSR
+        "in r24, 0x34",
+        "tst r24",
+        "brne 1f",
+        "mov r24, r2",
+        "1:",
+        "sts {flags}, r24",
+        // Clear MCUSR, since WDRF forces the watchdog on
+        "out 0x34, r1",
+        // Skip unless WDRF was set
+        "sbrs r24, 3",
+        "rjmp 2f",
+        // Timed sequence to disable the watchdog, with interrupts still disabled from reset
+        "ldi r24, 0x18",
+        "sts 0x60, r24",
+        "sts 0x60, r1",
+        "2:",
+        flags = sym RESET_FLAGS,
+        options(noreturn),
+    );
+}
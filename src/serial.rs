@@ -8,47 +8,262 @@
 //! println!("hello world!");
 //! ```
 //! Initializes the USART to a baud rate of 9600 and transmits "hello world"
+//! 
+//! ```no_run
+//! use atmega::serial::*;
+//! 
+//! let baud = Serial::begin_with(115200, Config::SERIAL_8E1).unwrap();
+//! println!("running at {} baud ({} tenths of a percent off)", baud.actual, baud.error);
+//! ```
+//! Initializes the USART to 115200 baud with even parity, and prints the baud rate that was actually achieved
 
 use crate::constants::CPU_FREQUENCY;
 use crate::registers::{ UBRR0H, UBRR0L, UCSR0A, UCSR0B, UCSR0C, UDR0, Register };
 use core::fmt::Write;
 
-#[cfg(any(feature = "serial-buffer", doc))]
-#[doc(cfg(feature = "serial-buffer"))]
+#[cfg(feature = "serial-tx-buffer")]
+use crate::interrupts::{ self, State };
+
+#[cfg(any(feature = "serial-tx-buffer", doc))]
 use crate::buffer::Buffer;
 #[cfg(any(feature = "serial-buffer", doc))]
-#[doc(cfg(feature = "serial-buffer"))]
+use crate::buffer::Queue;
 use crate::volatile::Volatile;
 
+#[cfg(any(feature = "millis", doc))]
+use crate::stream::Stream;
+
 #[cfg(any(feature = "serial-buffer", doc))]
 #[doc(cfg(feature = "serial-buffer"))]
-static USART_BUFFER: Volatile<Buffer<u8, 32>> = Volatile::new(Buffer::new());
+static USART_BUFFER: Queue<u8, 32> = Queue::new();
+
+#[cfg(feature = "serial-tx-buffer")]
+static USART_TX_BUFFER: Volatile<Buffer<u8, 64>> = Volatile::new(Buffer::new());
+/// Set once a byte has been transmitted, so `flush()` doesn't wait for a TXC0 that will never come.
+#[cfg(feature = "serial-tx-buffer")]
+static USART_WRITTEN: Volatile<bool> = Volatile::new(false);
+
+/// A byte that was read out of the data register by `Serial::peek()`, but not yet recieved.
+#[cfg(any(not(feature = "serial-buffer"), doc))]
+static USART_PEEK: Volatile<Option<u8>> = Volatile::new(None);
+
+/// The timeout used by the [`Stream`] implementation, in milliseconds.
+#[cfg(any(feature = "millis", doc))]
+static SERIAL_TIMEOUT: Volatile<u64> = Volatile::new(1000);
+
+/// The first reception error since the last call to `Serial::take_error()`.
+static USART_ERROR: Volatile<Option<Error>> = Volatile::new(None);
+
+/// Errors flagged by the USART while receiving a byte.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The stop bit of a received byte was not found (`FE0`).
+    Frame,
+    /// A byte was received while the USART receive buffer was full, so it was lost (`DOR0`).
+    Overrun,
+    /// The parity bit of a received byte did not match (`UPE0`).
+    Parity,
+}
+
+impl Error {
+    /// Reads the error flags out of a `UCSR0A` value.
+    fn from_status(status: u8) -> Option<Error> {
+        use UCSR0A::*;
+        if status & DOR0.bv() != 0 {
+            Some(Error::Overrun)
+        } else if status & FE0.bv() != 0 {
+            Some(Error::Frame)
+        } else if status & UPE0.bv() != 0 {
+            Some(Error::Parity)
+        } else {
+            None
+        }
+    }
+}
+
+/// The number of data bits in each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(missing_docs)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+}
+
+/// The parity bit sent after the data bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    /// No parity bit
+    None,
+    /// The parity bit makes the number of ones even
+    Even,
+    /// The parity bit makes the number of ones odd
+    Odd,
+}
+
+/// The number of stop bits at the end of each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(missing_docs)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// The format of each serial frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    /// The number of data bits
+    pub data_bits: DataBits,
+    /// The parity bit
+    pub parity: Parity,
+    /// The number of stop bits
+    pub stop_bits: StopBits,
+}
+
+impl Config {
+    /// 8 data bits, no parity, 1 stop bit
+    pub const SERIAL_8N1: Config = Config::new(DataBits::Eight, Parity::None, StopBits::One);
+    /// 8 data bits, no parity, 2 stop bits
+    pub const SERIAL_8N2: Config = Config::new(DataBits::Eight, Parity::None, StopBits::Two);
+    /// 8 data bits, even parity, 1 stop bit
+    pub const SERIAL_8E1: Config = Config::new(DataBits::Eight, Parity::Even, StopBits::One);
+    /// 8 data bits, odd parity, 1 stop bit
+    pub const SERIAL_8O1: Config = Config::new(DataBits::Eight, Parity::Odd, StopBits::One);
+    /// 7 data bits, even parity, 1 stop bit
+    pub const SERIAL_7E1: Config = Config::new(DataBits::Seven, Parity::Even, StopBits::One);
+
+    /// Creates a new frame format.
+    pub const fn new(data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Config {
+        Config { data_bits, parity, stop_bits }
+    }
+
+    /// The value of `UCSR0C` for this format, in asynchronous mode.
+    fn ucsr0c(&self) -> u8 {
+        use UCSR0C::*;
+
+        let size = match self.data_bits {
+            DataBits::Five => 0,
+            DataBits::Six => UCSZ00.bv(),
+            DataBits::Seven => UCSZ01.bv(),
+            DataBits::Eight | DataBits::Nine => UCSZ01.bv() | UCSZ00.bv(),
+        };
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Even => UPM01.bv(),
+            Parity::Odd => UPM01.bv() | UPM00.bv(),
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => USBS0.bv(),
+        };
+
+        size | parity | stop
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::SERIAL_8N1
+    }
+}
+
+/// The baud rate settings picked for a requested baud rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baud {
+    /// The value written to `UBRR0`
+    pub ubrr: u16,
+    /// Whether double speed mode (`U2X0`) is used
+    pub double_speed: bool,
+    /// The baud rate that is actually achieved
+    pub actual: u32,
+    /// The difference between the actual and requested baud rate, in tenths of a percent
+    pub error: i16,
+}
+
+impl Baud {
+    /// The slowest baud rate that can be reached, in normal mode with the largest `UBRR0`.
+    pub const MIN: u32 = (CPU_FREQUENCY / (16 * 4096)) as u32;
+    /// The fastest baud rate that can be reached, in double speed mode with a `UBRR0` of 0.
+    pub const MAX: u32 = (CPU_FREQUENCY / 8) as u32;
+
+    /// Picks whichever of normal or double speed mode gets closest to the requested baud rate.
+    /// Normal mode is preferred when both are equally close, since it samples each bit more times.
+    ///
+    /// Returns `None` if the baud rate is outside of [`Baud::MIN`] to [`Baud::MAX`].
+    pub fn new(baud: u32) -> Option<Baud> {
+        if baud == 0 || baud < Self::MIN || baud > Self::MAX {
+            return None;
+        }
+
+        let normal = Self::with_divisor(baud, 16);
+        let double = Self::with_divisor(baud, 8);
+
+        if double.error.unsigned_abs() < normal.error.unsigned_abs() {
+            Some(double)
+        } else {
+            Some(normal)
+        }
+    }
+
+    /// Calculates the closest `UBRR0` for the given clock divisor (16 for normal mode, 8 for double speed).
+    fn with_divisor(baud: u32, divisor: u64) -> Baud {
+        let scale = divisor * baud as u64;
+        // Round to the nearest value instead of truncating
+        let ubrr = ((CPU_FREQUENCY + scale/2) / scale).saturating_sub(1).min(0x0FFF);
+        let actual = (CPU_FREQUENCY / (divisor * (ubrr + 1))) as u32;
+        let error = (actual as i64 - baud as i64) * 1000 / baud as i64;
+
+        Baud {
+            ubrr: ubrr as u16,
+            double_speed: divisor == 8,
+            actual,
+            // Saturate, since a clamped UBRR0 can be far off
+            error: error.clamp(i16::MIN as i64, i16::MAX as i64) as i16,
+        }
+    }
+}
+
+/// Reads the error flags and then the data register, recording any error.
+/// The flags have to be read first, since reading `UDR0` clears them.
+#[inline(always)]
+unsafe fn read_data() -> u8 {
+    if let Some(error) = Error::from_status(UCSR0A::read()) {
+        if USART_ERROR.read().is_none() {
+            USART_ERROR.write(Some(error));
+        }
+    }
+    UDR0::read()
+}
 
 /// Easy interface with the USART with `core::fmt::Write` implemented.
 pub struct Serial;
 
 impl Serial {
-    /// Initialize serial at the given baud rate
+    /// Initialize serial at the given baud rate with 8 data bits, no parity, and 1 stop bit
+    ///
+    /// Does nothing if the baud rate can't be reached, use `begin_with()` to check.
     pub fn begin(baud: u32) {
-        let ubrr = ((CPU_FREQUENCY / (16*baud) as u64)-1) as u16;
-        unsafe {
-            // Write baud rate to UBRR
-            UBRR0H::write(((ubrr >> 8) & 0x0F) as u8);
-            UBRR0L::write((ubrr & 0xFF) as u8);
-
-            // Set async
-            UCSR0C::UMSEL00.clear();
+        Self::begin_with(baud, Config::default());
+    }
 
-            // Set single stop bit
-            UCSR0C::USBS0.clear();
+    /// Initialize serial at the given baud rate and frame format.
+    /// Returns the baud rate settings that were picked,
+    /// or `None` without changing anything if the baud rate is outside of [`Baud::MIN`] to [`Baud::MAX`].
+    pub fn begin_with(baud: u32, config: Config) -> Option<Baud> {
+        let rate = Baud::new(baud)?;
 
-            // Set parity disabled
-            UCSR0C::UPM00.clear();
-            UCSR0C::UPM01.clear();
+        unsafe {
+            // Write baud rate to UBRR
+            UBRR0H::write(((rate.ubrr >> 8) & 0x0F) as u8);
+            UBRR0L::write((rate.ubrr & 0xFF) as u8);
+            UCSR0A::U2X0.set_value(rate.double_speed);
 
-            // Eight bit data bit
-            UCSR0C::UCSZ00.set();
-            UCSR0C::UCSZ01.set();
+            // Set async, parity, stop bits, and the lower data size bits
+            UCSR0C::write(config.ucsr0c());
+            // The top data size bit lives in UCSR0B
+            UCSR0B::UCSZ02.set_value(config.data_bits == DataBits::Nine);
 
             // Enable Reciever and Transmitter
             UCSR0B::RXEN0.set();
@@ -58,6 +273,36 @@ impl Serial {
             #[cfg(feature = "serial-buffer")]
             UCSR0B::RXCIE0.set();
         }
+
+        USART_ERROR.write(None);
+
+        Some(rate)
+    }
+
+    /// Returns the first reception error since the last call, clearing it.
+    pub fn take_error() -> Result<(), Error> {
+        let error = USART_ERROR.read();
+        USART_ERROR.write(None);
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Transmits a 9 bit frame over serial.
+    /// Only the lower 9 bits are used, and serial must have been started with `DataBits::Nine`.
+    /// Blocking
+    pub fn transmit9(data: u16) {
+        #[cfg(feature = "serial-tx-buffer")]
+        Self::flush();
+
+        while !Self::_transmit_ready() {}
+        unsafe {
+            // The ninth bit has to be written before the data register
+            UCSR0B::TXB80.set_value(data & 0x100 != 0);
+            UDR0::write(data as u8);
+        }
     }
 
     /// Checks if the USART is ready to transmit the next byte.
@@ -67,16 +312,88 @@ impl Serial {
 
     /// Transmits byte over serial.
     /// Blocking
+    #[cfg(not(feature = "serial-tx-buffer"))]
     pub fn transmit(byte: u8) {
         while !Self::_transmit_ready() {}
         unsafe { UDR0::write(byte) };
     }
 
+    /// Queues a byte to be transmitted over serial.
+    /// Only blocks if the transmit buffer is full.
+    #[cfg(feature = "serial-tx-buffer")]
+    pub fn transmit(byte: u8) {
+        USART_WRITTEN.write(true);
+
+        // If the buffer and the data register are empty, just write the byte
+        // to the data register and be done. This shortcut helps
+        // significantly improve the effective datarate at high (>
+        // 500kbit/s) bitrates, where interrupt overhead becomes a slowdown.
+        let sent = interrupts::without(State::Restore, || {
+            if USART_TX_BUFFER.as_deref(|buf| buf.is_empty()) && Self::_transmit_ready() {
+                unsafe {
+                    UDR0::write(byte);
+                    clear_txc();
+                }
+                true
+            } else {
+                false
+            }
+        });
+        if sent {
+            return;
+        }
+
+        // If the output buffer is full, there's nothing for it other than to
+        // wait for the interrupt handler to empty it a bit
+        while USART_TX_BUFFER.as_deref(|buf| buf.available() == 0) {
+            if !interrupts::enabled() && Self::_transmit_ready() {
+                // Interrupts are disabled, so we'll have to poll the data
+                // register empty flag ourselves.
+                tx_udr_empty();
+            }
+        }
+
+        interrupts::without(State::Restore, || unsafe {
+            USART_TX_BUFFER.as_mut(|buf| buf.write(byte));
+            UCSR0B::UDRIE0.set();
+        });
+    }
+
+    /// Waits until all queued bytes have been transmitted.
+    #[cfg(any(feature = "serial-tx-buffer", doc))]
+    #[doc(cfg(feature = "serial-tx-buffer"))]
+    pub fn flush() {
+        // If we have never written a byte, no need to flush. This special
+        // case is needed since there is no way to force the TXC (transmit
+        // complete) bit to 1 during initialization
+        if !USART_WRITTEN.read() {
+            return;
+        }
+
+        while unsafe { UCSR0B::UDRIE0.read_bit() || !UCSR0A::TXC0.read_bit() } {
+            if !interrupts::enabled() && unsafe { UCSR0B::UDRIE0.read_bit() } && Self::_transmit_ready() {
+                // Interrupts are globally disabled, but the DR empty
+                // interrupt should be enabled, so poll the DR empty flag to
+                // prevent deadlock
+                tx_udr_empty();
+            }
+        }
+        // If we get here, nothing is queued anymore (DRIE is disabled) and
+        // the hardware finished transmission (TXC is set).
+    }
+
+    /// The number of bytes that can be queued before `transmit()` blocks.
+    #[cfg(any(feature = "serial-tx-buffer", doc))]
+    #[doc(cfg(feature = "serial-tx-buffer"))]
+    pub fn available_for_write() -> usize {
+        USART_TX_BUFFER.as_deref(|buf| buf.available())
+    }
+
     /// Checks if the USART has a byte to read.
     #[cfg(any(not(feature = "serial-buffer"), doc))]
     #[doc(cfg(not(feature = "serial-buffer")))]
     pub fn recieve_ready() -> bool {
-        unsafe { UCSR0A::RXC0.read_bit() }
+        USART_PEEK.read().is_some() || unsafe { UCSR0A::RXC0.read_bit() }
     }
 
     /// Waits for a byte to be recieved over serial.
@@ -84,33 +401,98 @@ impl Serial {
     #[cfg(any(not(feature = "serial-buffer"), doc))]
     #[doc(cfg(not(feature = "serial-buffer")))]
     pub fn recieve() -> u8 {
+        loop {
+            if let Some(byte) = Self::try_recieve() {
+                return byte;
+            }
+        }
+    }
+
+    /// Waits for a 9 bit frame to be recieved over serial.
+    /// Serial must have been started with `DataBits::Nine`.
+    /// Blocking
+    #[cfg(any(not(feature = "serial-buffer"), doc))]
+    #[doc(cfg(not(feature = "serial-buffer")))]
+    pub fn recieve9() -> u16 {
         while !Self::recieve_ready() {}
-        unsafe { UDR0::read() }
+        unsafe {
+            // The ninth bit has to be read before the data register
+            let ninth = UCSR0B::RXB80.read_bit();
+            ((ninth as u16) << 8) | read_data() as u16
+        }
     }
 
     /// Returns recieved data if there is any available.
     #[cfg(any(not(feature = "serial-buffer"), doc))]
     #[doc(cfg(not(feature = "serial-buffer")))]
     pub fn try_recieve() -> Option<u8> {
-        if Self::recieve_ready() {
-            Some(unsafe { UDR0::read() })
+        if let Some(byte) = USART_PEEK.read() {
+            USART_PEEK.write(None);
+            Some(byte)
+        } else if unsafe { UCSR0A::RXC0.read_bit() } {
+            Some(unsafe { read_data() })
         } else {
             None
         }
     }
 
+    /// Returns the next recieved byte without removing it, if there is one available.
+    #[cfg(not(feature = "serial-buffer"))]
+    pub fn peek() -> Option<u8> {
+        if USART_PEEK.read().is_none() && unsafe { UCSR0A::RXC0.read_bit() } {
+            USART_PEEK.write(Some(unsafe { read_data() }));
+        }
+        USART_PEEK.read()
+    }
+
     /// The total bytes stored in the USART buffer
     #[cfg(any(feature = "serial-buffer", doc))]
     #[doc(cfg(feature = "serial-buffer"))]
     pub fn len() -> u8 {
-        USART_BUFFER.read().len() as u8
+        USART_BUFFER.len() as u8
     }
 
     /// Read the byte at the front of the USART buffer
     #[cfg(any(feature = "serial-buffer", doc))]
     #[doc(cfg(feature = "serial-buffer"))]
     pub fn read() -> Option<u8> {
-        USART_BUFFER.read().read()
+        // SAFETY: only the main program consumes, and only `USART_RX` produces
+        unsafe { USART_BUFFER.consumer() }.dequeue()
+    }
+
+    /// Returns the byte at the front of the USART buffer without removing it.
+    #[cfg(feature = "serial-buffer")]
+    pub fn peek() -> Option<u8> {
+        unsafe { USART_BUFFER.consumer() }.peek()
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Stream for Serial {
+    fn available(&mut self) -> usize {
+        #[cfg(feature = "serial-buffer")]
+        { Serial::len() as usize }
+        #[cfg(not(feature = "serial-buffer"))]
+        { Serial::recieve_ready() as usize }
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        #[cfg(feature = "serial-buffer")]
+        { Serial::read() }
+        #[cfg(not(feature = "serial-buffer"))]
+        { Serial::try_recieve() }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        Serial::peek()
+    }
+
+    fn timeout(&self) -> u64 {
+        SERIAL_TIMEOUT.read()
+    }
+
+    fn set_timeout(&mut self, timeout: u64) {
+        SERIAL_TIMEOUT.write(timeout);
     }
 }
 
@@ -162,12 +544,47 @@ pub fn _print(args: ::core::fmt::Arguments) {
     (Serial{}).write_fmt(args);
 }
 
+/// Clears the transmit complete flag, which is done by writing a one to it.
+#[cfg(feature = "serial-tx-buffer")]
+#[inline(always)]
+unsafe fn clear_txc() {
+    use UCSR0A::*;
+    UCSR0A::write((UCSR0A::read() & (U2X0.bv() | MPCM0.bv())) | TXC0.bv());
+}
+
+/// Moves the next queued byte into the data register,
+/// disabling the data register empty interrupt once the buffer is empty.
+#[cfg(feature = "serial-tx-buffer")]
+#[inline(always)]
+fn tx_udr_empty() {
+    USART_TX_BUFFER.as_mut(|buf| unsafe {
+        if let Some(byte) = buf.read() {
+            UDR0::write(byte);
+            clear_txc();
+        }
+
+        if buf.is_empty() {
+            // Buffer empty, so disable interrupts
+            UCSR0B::UDRIE0.clear();
+        }
+    });
+}
+
+#[cfg(feature = "serial-tx-buffer")]
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_19"]
+pub unsafe extern "avr-interrupt" fn USART_UDRE() {
+    tx_udr_empty();
+}
+
 #[cfg(feature = "serial-buffer")]
 #[doc(hidden)]
 #[inline(always)]
 #[allow(non_snake_case)]
 #[export_name = "__vector_18"]
 pub unsafe extern "avr-interrupt" fn USART_RX() {
-    crate::wiring::digital_write(crate::wiring::Pin::D9, true);
-    USART_BUFFER.operate(|mut buf| { buf.write(UDR0::read()); buf });
+    // Bytes are dropped if the buffer is full
+    let _ = USART_BUFFER.producer().enqueue(read_data());
 }
\ No newline at end of file
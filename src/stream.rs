@@ -0,0 +1,247 @@
+//! Parsing and timeout-based reading of incoming data, matching the Arduino [Stream](https://www.arduino.cc/reference/en/language/functions/communication/stream/) class.
+//!
+//! Adapted from the official [source code](https://github.com/arduino/ArduinoCore-avr/blob/master/cores/arduino/Stream.cpp)
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::stream::{ Stream, Lookahead };
+//!
+//! Serial::begin(9600);
+//! Serial.set_timeout(5000);
+//!
+//! if let Some(value) = Serial.parse_int(Lookahead::SkipAll, None) {
+//!     println!("got {}", value);
+//! }
+//! ```
+
+use crate::timing::millis;
+
+/// How characters that aren't part of a number are treated while looking for one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookahead {
+    /// All invalid characters are skipped.
+    SkipAll,
+    /// Nothing is skipped, so parsing fails unless the next character is part of a number.
+    SkipNone,
+    /// Only tabs, spaces, line feeds, and carriage returns are skipped.
+    SkipWhitespace,
+}
+
+/// A source of bytes that can be read with a timeout.
+///
+/// Implementors only need to provide `available()`, `read()`, `peek()`, and access to the timeout.
+pub trait Stream {
+    /// The number of bytes that can be read without waiting.
+    fn available(&mut self) -> usize;
+
+    /// Reads the next byte, if there is one available.
+    fn read(&mut self) -> Option<u8>;
+
+    /// Returns the next byte without removing it, if there is one available.
+    fn peek(&mut self) -> Option<u8>;
+
+    /// The maximum milliseconds to wait for data.
+    fn timeout(&self) -> u64;
+
+    /// Sets the maximum milliseconds to wait for data.
+    /// Defaults to 1000.
+    fn set_timeout(&mut self, timeout: u64);
+
+    /// Reads the next byte, waiting up to the timeout for one to arrive.
+    fn timed_read(&mut self) -> Option<u8> {
+        let start = millis();
+        loop {
+            if let Some(byte) = self.read() {
+                return Some(byte);
+            }
+            if millis().wrapping_sub(start) >= self.timeout() {
+                return None;
+            }
+        }
+    }
+
+    /// Returns the next byte without removing it, waiting up to the timeout for one to arrive.
+    fn timed_peek(&mut self) -> Option<u8> {
+        let start = millis();
+        loop {
+            if let Some(byte) = self.peek() {
+                return Some(byte);
+            }
+            if millis().wrapping_sub(start) >= self.timeout() {
+                return None;
+            }
+        }
+    }
+
+    /// Returns the next byte that could start a number, discarding anything before it according to `lookahead`.
+    /// Returns `None` on timeout, or if a character can't be skipped.
+    fn peek_next_digit(&mut self, lookahead: Lookahead, detect_decimal: bool) -> Option<u8> {
+        loop {
+            let byte = self.timed_peek()?;
+
+            if byte == b'-' || byte.is_ascii_digit() || (detect_decimal && byte == b'.') {
+                return Some(byte);
+            }
+
+            match lookahead {
+                Lookahead::SkipAll => {},
+                Lookahead::SkipNone => return None,
+                Lookahead::SkipWhitespace => match byte {
+                    b' ' | b'\t' | b'\r' | b'\n' => {},
+                    _ => return None,
+                },
+            }
+
+            // Discard the non-numeric character
+            self.read();
+        }
+    }
+
+    /// Reads until the target is found, returning `true` if it was found before timing out.
+    fn find(&mut self, target: &[u8]) -> bool {
+        self.find_until(target, &[])
+    }
+
+    /// Reads until either the target or the terminator is found,
+    /// returning `true` only if the target was found first.
+    /// An empty terminator is never matched.
+    fn find_until(&mut self, target: &[u8], terminator: &[u8]) -> bool {
+        if target.is_empty() {
+            return true;
+        }
+
+        let mut target_index = 0;
+        let mut term_index = 0;
+
+        while let Some(byte) = self.timed_read() {
+            if byte == target[target_index] {
+                target_index += 1;
+                if target_index == target.len() {
+                    return true;
+                }
+            } else {
+                target_index = if byte == target[0] { 1 } else { 0 };
+            }
+
+            if !terminator.is_empty() {
+                if byte == terminator[term_index] {
+                    term_index += 1;
+                    if term_index == terminator.len() {
+                        return false;
+                    }
+                } else {
+                    term_index = if byte == terminator[0] { 1 } else { 0 };
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Reads bytes into the buffer until it is full or the read times out.
+    /// Returns the number of bytes read.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < buf.len() {
+            match self.timed_read() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Reads bytes into the buffer until the terminator is found, the buffer is full, or the read times out.
+    /// The terminator is removed from the stream, but not stored in the buffer.
+    /// Returns the number of bytes read.
+    fn read_bytes_until(&mut self, terminator: u8, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < buf.len() {
+            match self.timed_read() {
+                Some(byte) if byte == terminator => break,
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Reads a single line into the buffer, returning the line without the `\n` or `\r\n` ending.
+    /// Stops early if the buffer is full or the read times out.
+    fn read_line<'a>(&mut self, buf: &'a mut [u8]) -> &'a [u8] {
+        let mut len = self.read_bytes_until(b'\n', buf);
+        if len > 0 && buf[len - 1] == b'\r' {
+            len -= 1;
+        }
+        &buf[..len]
+    }
+
+    /// Parses the next integer in the stream, skipping characters before it according to `lookahead`.
+    /// Any occurences of `ignore` inside the number are skipped, allowing for thousands separators.
+    ///
+    /// Returns `None` if no number was found before timing out.
+    fn parse_int(&mut self, lookahead: Lookahead, ignore: Option<u8>) -> Option<i32> {
+        let mut negative = false;
+        let mut value: i32 = 0;
+
+        let mut byte = self.peek_next_digit(lookahead, false)?;
+        loop {
+            if Some(byte) == ignore {
+                // Ignore this character
+            } else if byte == b'-' {
+                negative = true;
+            } else if byte.is_ascii_digit() {
+                value = value.wrapping_mul(10).wrapping_add((byte - b'0') as i32);
+            }
+            // Consume the character we got with peek
+            self.read();
+
+            match self.timed_peek() {
+                Some(next) if next.is_ascii_digit() || Some(next) == ignore => byte = next,
+                _ => break,
+            }
+        }
+
+        Some(if negative { value.wrapping_neg() } else { value })
+    }
+
+    /// Parses the next floating point number in the stream, skipping characters before it according to `lookahead`.
+    /// Any occurences of `ignore` inside the number are skipped.
+    ///
+    /// Returns `None` if no number was found before timing out.
+    fn parse_float(&mut self, lookahead: Lookahead, ignore: Option<u8>) -> Option<f32> {
+        let mut negative = false;
+        let mut is_fraction = false;
+        let mut value: f32 = 0.0;
+        let mut fraction: f32 = 1.0;
+
+        let mut byte = self.peek_next_digit(lookahead, true)?;
+        loop {
+            if Some(byte) == ignore {
+                // Ignore this character
+            } else if byte == b'-' {
+                negative = true;
+            } else if byte == b'.' {
+                is_fraction = true;
+            } else if byte.is_ascii_digit() {
+                if is_fraction {
+                    fraction *= 0.1;
+                    value += fraction * (byte - b'0') as f32;
+                } else {
+                    value = value * 10.0 + (byte - b'0') as f32;
+                }
+            }
+            // Consume the character we got with peek
+            self.read();
+
+            match self.timed_peek() {
+                Some(next) if next.is_ascii_digit() || (next == b'.' && !is_fraction) || Some(next) == ignore => byte = next,
+                _ => break,
+            }
+        }
+
+        Some(if negative { -value } else { value })
+    }
+}
@@ -1,10 +1,43 @@
 //! Utilities for reading and controlling time
+//!
+//! [`Instant`] and [`Duration`] measure time in microseconds with 32-bit math, and handle `micros()` wrapping around.
+//! [`Deadline`] wraps them up for polling loops that need to give up after a while.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::timing::{ Deadline, Duration, Instant };
+//!
+//! let mut last = Instant::now();
+//! loop {
+//!     if last.elapsed() >= Duration::from_millis(500) {
+//!         last += Duration::from_millis(500);
+//!         digital_toggle(Pin::D13);
+//!     }
+//!
+//!     let deadline = Deadline::after(Duration::from_millis(10));
+//!     while !Serial::recieve_ready() {
+//!         if deadline.is_expired() {
+//!             break;
+//!         }
+//!     }
+//! }
+//! ```
 
-use core::arch::asm;
+use core::ops::{ Add, AddAssign, Sub, SubAssign };
+
+// Host tests (`cargo test --lib`) only build the parts that don't touch the hardware
+#[cfg(any(feature = "millis", not(test)))]
 use crate::constants::CPU_FREQUENCY;
+#[cfg(not(test))]
+use core::arch::asm;
+#[cfg(not(test))]
 use crate::registers::{ Register, TCNT0 };
 
-#[cfg(feature = "millis")]
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+use crate::interrupts::{ self, State };
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+use crate::registers::TIFR0;
+#[cfg(all(any(feature = "millis", doc), not(test)))]
 use crate::volatile::Volatile;
 
 /// Milliseconds in a second
@@ -20,6 +53,7 @@ pub const MICROS: u64 = 1_000_000;
 /// Thus, at a CPU speed of 1MHZ, delays of up to about 262.1 
 /// milliseconds can be achieved
 #[inline(always)]
+#[cfg(not(test))]
 pub fn _delay_loop(count: u16) {
     unsafe {
         asm!(
@@ -33,6 +67,7 @@ pub fn _delay_loop(count: u16) {
 /// Delay the specified CPU cycles using _delay_loop()
 /// Has a precision of 4 cycles.
 #[inline(always)]
+#[cfg(not(test))]
 pub fn delay_cycles(cycles: u64) {
     // Each iteration in _delay_loop() takes 4 clock cycles
     let loops = cycles/4;
@@ -51,22 +86,26 @@ pub fn delay_cycles(cycles: u64) {
 /// Delay the specified number of microseconds
 /// On boards with a clock speed of less than 4MHz the precision will be less than 1us
 #[inline(always)]
+#[cfg(not(test))]
 pub fn delay_micros(us: u64) {
     delay_cycles(us * (CPU_FREQUENCY/MICROS));
 }
 
 /// Delay the specified number of milliseconds
 #[inline(always)]
+#[cfg(not(test))]
 pub fn delay(ms: u64) {
     delay_cycles(ms * (CPU_FREQUENCY/MILLIS));
 }
 
-#[cfg(feature = "millis")]
+#[cfg(all(any(feature = "millis", doc), not(test)))]
 static SYSTICK: Volatile<u64> = Volatile::new(0);
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+static TIMER0_HOOK: Volatile<Option<fn()>> = Volatile::new(None);
 
 /// The total milliseconds since system boot.
 #[inline]
-#[cfg(any(feature = "millis", doc))]
+#[cfg(all(any(feature = "millis", doc), not(test)))]
 #[doc(cfg(feature = "millis"))]
 pub fn millis() -> u64 {
     SYSTICK.read().wrapping_mul(64 * 256) / (CPU_FREQUENCY/MILLIS)
@@ -75,18 +114,386 @@ pub fn millis() -> u64 {
 /// The number of microseconds that have passed since system boot.
 /// Has a precision of 4us on a 16MHz chip.
 #[inline]
-#[cfg(any(feature = "millis", doc))]
+#[cfg(all(any(feature = "millis", doc), not(test)))]
 #[doc(cfg(feature = "millis"))]
 pub fn micros() -> u64 {
     let timer = unsafe { TCNT0::read() };
     (SYSTICK.read().wrapping_mul(64 * 256) / (CPU_FREQUENCY/MICROS)) + (timer as u64 * 4)
 }
 
-#[cfg(feature = "millis")]
+/// Calls the given function from the `TIMER0_OVF` interrupt after `millis()` is updated.
+/// Since the `millis` feature owns the interrupt, this is the only way to run code on each overflow.
+/// Replaces any previous hook.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+#[doc(cfg(feature = "millis"))]
+pub fn on_timer0_overflow(hook: fn()) {
+    TIMER0_HOOK.write(Some(hook));
+}
+
+/// Removes the hook set by `on_timer0_overflow()`.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+#[doc(cfg(feature = "millis"))]
+pub fn clear_timer0_overflow() {
+    TIMER0_HOOK.write(None);
+}
+
+#[cfg(all(feature = "millis", not(test)))]
 #[doc(hidden)]
 #[inline(always)]
 #[allow(non_snake_case)]
 #[export_name = "__vector_16"]
 pub unsafe extern "avr-interrupt" fn TIMER0_OVF() {
     SYSTICK.operate(|val| val + 1);
+
+    if let Some(hook) = TIMER0_HOOK.read() {
+        hook();
+    }
+}
+
+/// Microseconds per `TCNT0` tick, with the prescaler of 64 set up by `wiring::_init()`.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+const MICROS_PER_TICK: u32 = (64 * MICROS / CPU_FREQUENCY) as u32;
+/// Microseconds per Timer 0 overflow, which is how often `SYSTICK` is incremented.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+const MICROS_PER_OVERFLOW: u32 = MICROS_PER_TICK * 256;
+
+// `Instant` counts whole microseconds per tick, so any remainder would make it drift
+#[cfg(any(feature = "millis", doc))]
+const _: () = assert!(
+    64 * MICROS % CPU_FREQUENCY == 0,
+    "`Instant` needs a CPU frequency that divides 64MHz evenly, such as 16MHz or 8MHz"
+);
+
+/// A span of time in microseconds, up to about 71 minutes.
+///
+/// Constructors saturate at [`Duration::MAX`] instead of overflowing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration(u32);
+
+impl Duration {
+    /// No time at all.
+    pub const ZERO: Duration = Duration(0);
+    /// The longest duration that can be stored, just under 72 minutes.
+    pub const MAX: Duration = Duration(u32::MAX);
+
+    /// Creates a `Duration` from microseconds.
+    #[inline(always)]
+    pub const fn from_micros(us: u32) -> Duration {
+        Duration(us)
+    }
+
+    /// Creates a `Duration` from milliseconds.
+    #[inline(always)]
+    pub const fn from_millis(ms: u32) -> Duration {
+        Duration(ms.saturating_mul(MILLIS as u32))
+    }
+
+    /// Creates a `Duration` from seconds.
+    #[inline(always)]
+    pub const fn from_secs(secs: u32) -> Duration {
+        Duration(secs.saturating_mul(MICROS as u32))
+    }
+
+    /// The total whole microseconds.
+    #[inline(always)]
+    pub const fn as_micros(self) -> u32 {
+        self.0
+    }
+
+    /// The total whole milliseconds.
+    #[inline(always)]
+    pub const fn as_millis(self) -> u32 {
+        self.0 / MILLIS as u32
+    }
+
+    /// The total whole seconds.
+    #[inline(always)]
+    pub const fn as_secs(self) -> u32 {
+        self.0 / MICROS as u32
+    }
+
+    /// Returns `true` if the duration is zero.
+    #[inline(always)]
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Adds two durations, returning `None` on overflow.
+    #[inline(always)]
+    pub const fn checked_add(self, rhs: Duration) -> Option<Duration> {
+        match self.0.checked_add(rhs.0) {
+            Some(us) => Some(Duration(us)),
+            None => None,
+        }
+    }
+
+    /// Subtracts two durations, returning `None` if `rhs` is longer.
+    #[inline(always)]
+    pub const fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+        match self.0.checked_sub(rhs.0) {
+            Some(us) => Some(Duration(us)),
+            None => None,
+        }
+    }
+
+    /// Adds two durations, stopping at [`Duration::MAX`].
+    #[inline(always)]
+    pub const fn saturating_add(self, rhs: Duration) -> Duration {
+        Duration(self.0.saturating_add(rhs.0))
+    }
+
+    /// Subtracts two durations, stopping at [`Duration::ZERO`].
+    #[inline(always)]
+    pub const fn saturating_sub(self, rhs: Duration) -> Duration {
+        Duration(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    /// # Panics
+    /// Panics on overflow, use [`Duration::checked_add()`] to avoid this.
+    fn add(self, rhs: Duration) -> Duration {
+        self.checked_add(rhs).expect("overflow when adding durations")
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    /// # Panics
+    /// Panics if `rhs` is longer, use [`Duration::checked_sub()`] to avoid this.
+    fn sub(self, rhs: Duration) -> Duration {
+        self.checked_sub(rhs).expect("overflow when subtracting durations")
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+/// A point in time, measured in microseconds since boot.
+///
+/// The count wraps around about every 71 minutes, which is handled as long as the instants being compared are less than that apart.
+/// The CPU frequency must divide 64MHz evenly (e.g. 16MHz or 8MHz, but not 20MHz), or the build fails.
+#[cfg(any(feature = "millis", doc))]
+#[doc(cfg(feature = "millis"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instant(u32);
+
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+impl Instant {
+    /// Returns the current time.
+    /// Has a precision of 4us on a 16MHz chip.
+    pub fn now() -> Instant {
+        let (mut overflows, ticks) = interrupts::without(State::Restore, || unsafe {
+            let overflows = SYSTICK.read() as u32;
+            let ticks = TCNT0::read();
+
+            // The timer can overflow after interrupts are disabled, before SYSTICK is updated
+            if TIFR0::TOV0.read_bit() && ticks < 255 {
+                (overflows.wrapping_add(1), ticks)
+            } else {
+                (overflows, ticks)
+            }
+        });
+
+        overflows = overflows.wrapping_mul(MICROS_PER_OVERFLOW);
+        Instant(overflows.wrapping_add(ticks as u32 * MICROS_PER_TICK))
+    }
+
+    /// The time that has passed since this instant.
+    #[inline]
+    pub fn elapsed(self) -> Duration {
+        Instant::now().duration_since(self)
+    }
+
+    /// Returns `true` if at least `duration` has passed since this instant.
+    #[inline]
+    pub fn has_elapsed(self, duration: Duration) -> bool {
+        self.elapsed() >= duration
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Instant {
+    /// The time from `earlier` to this instant.
+    /// Returns a very long duration if `earlier` is actually later.
+    #[inline(always)]
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration(self.0.wrapping_sub(earlier.0))
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_add(rhs.0))
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+#[cfg(any(feature = "millis", doc))]
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A point in the future to give up waiting, for polling loops with a timeout.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+#[doc(cfg(feature = "millis"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deadline {
+    start: Instant,
+    timeout: Option<Duration>,
+}
+
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+impl Deadline {
+    /// Creates a deadline that expires once `timeout` has passed.
+    #[inline]
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline { start: Instant::now(), timeout: Some(timeout) }
+    }
+
+    /// Creates a deadline that never expires.
+    #[inline]
+    pub fn never() -> Deadline {
+        Deadline { start: Instant::now(), timeout: None }
+    }
+
+    /// Creates a deadline that expires after `timeout`, or never if `timeout` is zero,
+    /// matching timeouts where zero means disabled.
+    #[inline]
+    pub fn after_or_never(timeout: Duration) -> Deadline {
+        if timeout.is_zero() { Deadline::never() } else { Deadline::after(timeout) }
+    }
+
+    /// Returns `true` if the deadline has passed.
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        match self.timeout {
+            Some(timeout) => self.start.has_elapsed(timeout),
+            None => false,
+        }
+    }
+
+    /// The time left until the deadline, or `None` if it never expires.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.timeout.map(|timeout| timeout.saturating_sub(self.start.elapsed()))
+    }
+
+    /// Starts the deadline over from now, keeping the same timeout.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.start = Instant::now();
+    }
+}
+
+/// Delay for the given duration, using `Instant` instead of counting cycles.
+/// Interrupts keep running, and time spent in them is counted.
+#[cfg(all(any(feature = "millis", doc), not(test)))]
+#[doc(cfg(feature = "millis"))]
+pub fn delay_for(duration: Duration) {
+    let deadline = Deadline::after(duration);
+    while !deadline.is_expired() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constructors() {
+        assert_eq!(Duration::from_micros(1_500).as_millis(), 1);
+        assert_eq!(Duration::from_millis(1_500).as_micros(), 1_500_000);
+        assert_eq!(Duration::from_secs(2).as_millis(), 2_000);
+        assert!(Duration::from_millis(0).is_zero());
+    }
+
+    #[test]
+    fn constructors_saturate() {
+        assert_eq!(Duration::from_millis(u32::MAX), Duration::MAX);
+        assert_eq!(Duration::from_secs(4_295), Duration::MAX);
+        assert_eq!(Duration::from_secs(4_294).as_secs(), 4_294);
+        assert_eq!(Duration::from_millis(4_294_967).as_millis(), 4_294_967);
+        assert_eq!(Duration::from_millis(4_294_968), Duration::MAX);
+    }
+
+    #[test]
+    fn checked() {
+        let one = Duration::from_micros(1);
+        assert_eq!(Duration::MAX.checked_add(one), None);
+        assert_eq!(Duration::MAX.checked_add(Duration::ZERO), Some(Duration::MAX));
+        assert_eq!(Duration::ZERO.checked_sub(one), None);
+        assert_eq!(one.checked_sub(one), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn saturating() {
+        let one = Duration::from_micros(1);
+        assert_eq!(Duration::MAX.saturating_add(one), Duration::MAX);
+        assert_eq!(Duration::ZERO.saturating_sub(one), Duration::ZERO);
+        assert_eq!(one.saturating_add(one), Duration::from_micros(2));
+        assert_eq!(Duration::from_millis(3).saturating_sub(Duration::from_millis(1)), Duration::from_millis(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_overflow() {
+        let _ = Duration::MAX + Duration::from_micros(1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn sub_overflow() {
+        let _ = Duration::ZERO - Duration::from_micros(1);
+    }
+
+    #[test]
+    #[cfg(feature = "millis")]
+    fn instant_wraparound() {
+        let before = Instant(u32::MAX - 99);
+        let after = before + Duration::from_micros(250);
+        assert_eq!(after, Instant(150));
+        assert_eq!(after.duration_since(before), Duration::from_micros(250));
+        assert_eq!(after - before, Duration::from_micros(250));
+        assert_eq!(after - Duration::from_micros(250), before);
+    }
 }
@@ -6,6 +6,7 @@ use core::ptr::{ write_volatile, read_volatile };
 
 /// A dead simple safe(ish) mutable global variable.
 /// Used for communicating to and from interrupts.
+/// Every read copies the whole value, so data that isn't `Copy` or is large should use [`Mutex`](crate::interrupts::Mutex) instead.
 /// 
 /// SAFETY: the ATmega328p is a strictly single-threaded processor. Interrupts are disabled during all operations.
 pub struct Volatile<T: Copy>(UnsafeCell<T>);
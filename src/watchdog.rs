@@ -0,0 +1,148 @@
+//! The watchdog timer, which resets the chip or calls an interrupt if it isn't fed in time.
+//!
+//! The watchdog runs from a separate 128kHz oscillator, so timeouts are approximate.
+//! If the chip was reset by the watchdog, it is disabled during startup (see [`reset`](crate::reset)) so a short timeout can't cause a reset loop.
+//!
+//! # Example
+//! ```rust,no_run
+//! use atmega::watchdog::{ self, Timeout };
+//!
+//! watchdog::enable(Timeout::Ms500);
+//!
+//! loop {
+//!     // Resets the chip if this loop ever takes longer than 500ms
+//!     watchdog::feed();
+//! }
+//! ```
+
+use core::arch::asm;
+use crate::interrupts::{ self, State };
+use crate::registers::{ Register, WDTCSR, MCUSR };
+
+#[cfg(any(feature = "watchdog-interrupt", doc))]
+use crate::volatile::Volatile;
+
+#[cfg(feature = "watchdog-interrupt")]
+static WATCHDOG_HANDLER: Volatile<Option<fn()>> = Volatile::new(None);
+
+/// The time the watchdog waits to be fed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timeout {
+    /// 16 milliseconds
+    Ms16 = 0,
+    /// 32 milliseconds
+    Ms32 = 1,
+    /// 64 milliseconds
+    Ms64 = 2,
+    /// 125 milliseconds
+    Ms125 = 3,
+    /// 250 milliseconds
+    Ms250 = 4,
+    /// 500 milliseconds
+    Ms500 = 5,
+    /// 1 second
+    S1 = 6,
+    /// 2 seconds
+    S2 = 7,
+    /// 4 seconds
+    S4 = 8,
+    /// 8 seconds
+    S8 = 9,
+}
+
+impl Timeout {
+    /// The prescaler bits of `WDTCSR`, since `WDP3` is separate from the others.
+    fn bits(self) -> u8 {
+        let prescaler = self as u8;
+        (prescaler & 0b0111) | ((prescaler & 0b1000) << 2)
+    }
+}
+
+/// What happens when the watchdog times out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The chip is reset.
+    Reset,
+    /// The `WDT` interrupt is called, and the watchdog keeps running.
+    Interrupt,
+    /// The `WDT` interrupt is called, and the chip is reset on the next timeout.
+    /// The hardware switches to `Reset` mode when the interrupt is called,
+    /// so calling `enable_with()` again from the interrupt will keep it from resetting.
+    InterruptReset,
+}
+
+/// Starts the watchdog in reset mode.
+pub fn enable(timeout: Timeout) {
+    enable_with(timeout, Mode::Reset);
+}
+
+/// Starts the watchdog in the given mode.
+///
+/// The interrupt modes need a `WDT` handler, either from [`attach()`] with the `watchdog-interrupt` feature,
+/// or from the `#[interrupt]` macro. Otherwise a timeout will jump to the default handler, which resets the chip.
+pub fn enable_with(timeout: Timeout, mode: Mode) {
+    let mode = match mode {
+        Mode::Reset => WDTCSR::WDE.bv(),
+        Mode::Interrupt => WDTCSR::WDIE.bv(),
+        Mode::InterruptReset => WDTCSR::WDIE.bv() | WDTCSR::WDE.bv(),
+    };
+
+    unsafe { timed_write(timeout.bits() | mode) };
+}
+
+/// Resets the watchdog timer, starting the timeout over.
+#[inline(always)]
+pub fn feed() {
+    unsafe { asm!("wdr", options(nomem, nostack)) };
+}
+
+/// Stops the watchdog.
+pub fn disable() {
+    interrupts::without(State::Restore, || unsafe {
+        // WDE is forced on while WDRF is set
+        MCUSR::WDRF.clear();
+    });
+    unsafe { timed_write(0) };
+}
+
+/// Writes to `WDTCSR` using the timed sequence, where the new value has to be written within 4 cycles of setting `WDCE`.
+#[inline(always)]
+unsafe fn timed_write(value: u8) {
+    let change = WDTCSR::WDCE.bv() | WDTCSR::WDE.bv();
+
+    interrupts::without(State::Restore, || {
+        asm!(
+            "wdr",
+            "sts 0x60, {change}",
+            "sts 0x60, {value}",
+            change = in(reg) change,
+            value = in(reg) value,
+            options(nostack),
+        );
+    });
+}
+
+/// Calls the handler from the `WDT` interrupt whenever the watchdog times out in an interrupt mode.
+#[cfg(any(feature = "watchdog-interrupt", doc))]
+#[doc(cfg(feature = "watchdog-interrupt"))]
+pub fn attach(handler: fn()) {
+    WATCHDOG_HANDLER.write(Some(handler));
+}
+
+/// Removes the handler set by [`attach()`].
+#[cfg(any(feature = "watchdog-interrupt", doc))]
+#[doc(cfg(feature = "watchdog-interrupt"))]
+pub fn detach() {
+    WATCHDOG_HANDLER.write(None);
+}
+
+#[cfg(feature = "watchdog-interrupt")]
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_6"]
+pub unsafe extern "avr-interrupt" fn WDT() {
+    if let Some(handler) = WATCHDOG_HANDLER.read() {
+        handler();
+    }
+}
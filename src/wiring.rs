@@ -4,6 +4,14 @@
 
 use crate::registers::*;
 
+#[cfg(any(feature = "external-interrupt", doc))]
+use crate::interrupts::{ self, State };
+#[cfg(any(feature = "external-interrupt", doc))]
+use crate::volatile::Volatile;
+
+#[cfg(feature = "external-interrupt")]
+static EXTERNAL_HANDLERS: [Volatile<Option<fn()>>; 2] = [Volatile::new(None), Volatile::new(None)];
+
 /// Initializes timers for PWM
 pub fn _init() {
     unsafe {
@@ -455,3 +463,115 @@ pub fn analog_write(pin: Pin, value: u8) {
         digital_write(pin, value >= 128)
     }
 } 
+
+
+/// The pin condition that triggers an external interrupt.
+#[cfg(any(feature = "external-interrupt", doc))]
+#[doc(cfg(feature = "external-interrupt"))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Trigger {
+    /// Triggers continuously while the pin is LOW
+    Low = 0,
+    /// Triggers whenever the pin changes value
+    Change = 1,
+    /// Triggers when the pin goes from HIGH to LOW
+    Falling = 2,
+    /// Triggers when the pin goes from LOW to HIGH
+    Rising = 3,
+}
+
+/// Returns the external interrupt number of the given pin.
+/// Only `D2` (`INT0`) and `D3` (`INT1`) have external interrupts.
+#[cfg(any(feature = "external-interrupt", doc))]
+fn external_interrupt(pin: Pin) -> Option<usize> {
+    match pin {
+        Pin::D2 => Some(0),
+        Pin::D3 => Some(1),
+        _ => None,
+    }
+}
+
+/// Calls `handler` from an interrupt whenever `trigger` occurs on the given pin.
+/// Replaces any handler previously attached to the pin.
+/// 
+/// Returns `Err` if the pin does not have an external interrupt.
+/// Only `D2` and `D3` are supported.
+/// 
+/// # Example
+/// ```rust,no_run
+/// fn pressed() {
+///     digital_toggle(Pin::D13);
+/// }
+/// 
+/// pin_mode(Pin::D2, PinMode::INPUT_PULLUP);
+/// attach_interrupt(Pin::D2, Trigger::Falling, pressed).unwrap();
+/// ```
+#[cfg(any(feature = "external-interrupt", doc))]
+#[doc(cfg(feature = "external-interrupt"))]
+pub fn attach_interrupt(pin: Pin, trigger: Trigger, handler: fn()) -> Result<(), ()> {
+    let int = external_interrupt(pin).ok_or(())?;
+    let isc = trigger as u8;
+
+    EXTERNAL_HANDLERS[int].write(Some(handler));
+
+    interrupts::without(State::Restore, || unsafe {
+        match int {
+            0 => {
+                EICRA::ISC00.set_value(isc & 0b01 != 0);
+                EICRA::ISC01.set_value(isc & 0b10 != 0);
+                // Changing the sense control can set the flag, so clear it before enabling
+                EIFR::write(EIFR::INTF0.bv());
+                EIMSK::INT0.set();
+            },
+            _ => {
+                EICRA::ISC10.set_value(isc & 0b01 != 0);
+                EICRA::ISC11.set_value(isc & 0b10 != 0);
+                EIFR::write(EIFR::INTF1.bv());
+                EIMSK::INT1.set();
+            },
+        }
+    });
+
+    Ok(())
+}
+
+/// Disables the external interrupt on the given pin and removes its handler.
+/// 
+/// Returns `Err` if the pin does not have an external interrupt.
+#[cfg(any(feature = "external-interrupt", doc))]
+#[doc(cfg(feature = "external-interrupt"))]
+pub fn detach_interrupt(pin: Pin) -> Result<(), ()> {
+    let int = external_interrupt(pin).ok_or(())?;
+
+    unsafe {
+        match int {
+            0 => EIMSK::INT0.clear(),
+            _ => EIMSK::INT1.clear(),
+        }
+    }
+    EXTERNAL_HANDLERS[int].write(None);
+
+    Ok(())
+}
+
+#[cfg(feature = "external-interrupt")]
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_1"]
+pub unsafe extern "avr-interrupt" fn INT0() {
+    if let Some(handler) = EXTERNAL_HANDLERS[0].read() {
+        handler();
+    }
+}
+
+#[cfg(feature = "external-interrupt")]
+#[doc(hidden)]
+#[inline(always)]
+#[allow(non_snake_case)]
+#[export_name = "__vector_2"]
+pub unsafe extern "avr-interrupt" fn INT1() {
+    if let Some(handler) = EXTERNAL_HANDLERS[1].read() {
+        handler();
+    }
+}